@@ -0,0 +1,280 @@
+use std::path::{Path, PathBuf};
+
+use crate::{editor::{serde::SerdeLevel, validate::report_levels, components::ShapeType, format::format_for_path, migrate::migrate_levels, diff::{diff_levels, merge_levels}}, game::level::{level_paths, LevelRect}, save::{SaveProfile, FileStorage, SAVE_DIR}};
+
+pub const USAGE: &str = "\
+Usage:
+  spiderman [--record <file> | --replay <file> | --headless]
+  spiderman play <level>                 start straight in a level
+  spiderman edit <level>                 open a level in the editor
+  spiderman validate [<level>...]        check levels, every level by default (also --validate)
+  spiderman fmt <level> [--minify] [-o <out>]
+  spiderman convert <level> <out>        .json, .ron or .lvl (binary) from the extension
+  spiderman merge <level>... -o <out>
+  spiderman diff <old> <new>             added, removed and changed shapes
+  spiderman merge3 <base> <ours> <theirs> [-o <out>]
+                                         three-way merge into <ours> by default, works as a git merge driver
+  spiderman stats [<level>...]
+  spiderman migrate [<level>...]         update old level files, keeping their save data";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    // Runs the game, optionally skipping the menus
+    Launch { level: Option<PathBuf>, edit: bool },
+    Validate { paths: Vec<PathBuf> },
+    Format { path: PathBuf, minify: bool, output: Option<PathBuf> },
+    Convert { input: PathBuf, output: PathBuf },
+    Merge { inputs: Vec<PathBuf>, output: PathBuf },
+    Diff { before: PathBuf, after: PathBuf },
+    Merge3 { base: PathBuf, ours: PathBuf, theirs: PathBuf, output: Option<PathBuf> },
+    Stats { paths: Vec<PathBuf> },
+    Migrate { paths: Vec<PathBuf> },
+    Help,
+}
+
+/// Takes `-o <out>` out of `args`
+fn take_output(args: &mut Vec<String>) -> Result<Option<PathBuf>, String> {
+    let index = match args.iter().position(|arg| arg == "-o" || arg == "--output") {
+        Some(index) => index,
+        None => return Ok(None),
+    };
+    if index + 1 >= args.len() {
+        return Err("-o needs a path".to_string());
+    }
+    let output = args.remove(index + 1);
+    args.remove(index);
+    Ok(Some(PathBuf::from(output)))
+}
+
+fn one_path(args: &[String], command: &str) -> Result<PathBuf, String> {
+    match args {
+        [path] => Ok(PathBuf::from(path)),
+        _ => Err(format!("{} takes one level", command)),
+    }
+}
+
+impl Command {
+    /// Parses the arguments after the program name. Anything not starting with
+    /// a subcommand launches the game, which reads its own flags.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        // The flag validation had before there were subcommands, kept for scripts using it
+        if let Some(index) = args.iter().position(|arg| arg == "--validate") {
+            let paths = args[index + 1..].iter().take_while(|arg| !arg.starts_with('-')).map(PathBuf::from).collect();
+            return Ok(Command::Validate { paths });
+        }
+
+        let (command, rest) = match args.split_first() {
+            Some((command, rest)) if !command.starts_with('-') => (command.as_str(), rest),
+            _ => return Ok(Command::Launch { level: None, edit: false }),
+        };
+        let mut rest = rest.to_vec();
+
+        match command {
+            "play" | "edit" => Ok(Command::Launch { level: Some(one_path(&rest, command)?), edit: command == "edit" }),
+            "validate" => Ok(Command::Validate { paths: rest.iter().map(PathBuf::from).collect() }),
+            "fmt" => {
+                let output = take_output(&mut rest)?;
+                let minify = rest.iter().any(|arg| arg == "--minify");
+                rest.retain(|arg| arg != "--minify");
+                Ok(Command::Format { path: one_path(&rest, command)?, minify, output })
+            },
+            "convert" => match rest.as_slice() {
+                [input, output] => Ok(Command::Convert { input: PathBuf::from(input), output: PathBuf::from(output) }),
+                _ => Err("convert takes a level and an output path".to_string()),
+            },
+            "merge" => {
+                let output = take_output(&mut rest)?.ok_or("merge needs -o <out>")?;
+                if rest.len() < 2 {
+                    return Err("merge takes at least two levels".to_string());
+                }
+                Ok(Command::Merge { inputs: rest.iter().map(PathBuf::from).collect(), output })
+            },
+            "diff" => match rest.as_slice() {
+                [before, after] => Ok(Command::Diff { before: PathBuf::from(before), after: PathBuf::from(after) }),
+                _ => Err("diff takes two levels".to_string()),
+            },
+            "merge3" => {
+                let output = take_output(&mut rest)?;
+                match rest.as_slice() {
+                    [base, ours, theirs] => Ok(Command::Merge3 { base: PathBuf::from(base), ours: PathBuf::from(ours), theirs: PathBuf::from(theirs), output }),
+                    _ => Err("merge3 takes a base, our and their level".to_string()),
+                }
+            },
+            "stats" => Ok(Command::Stats { paths: rest.iter().map(PathBuf::from).collect() }),
+            "migrate" => Ok(Command::Migrate { paths: rest.iter().map(PathBuf::from).collect() }),
+            "help" => Ok(Command::Help),
+            _ => Err(format!("unknown command {}", command)),
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<SerdeLevel, String> {
+    SerdeLevel::load(path).map_err(|e| format!("couldn't load {}: {}", path.display(), e))
+}
+
+fn save(path: &Path, level: &SerdeLevel, pretty: bool) -> Result<(), String> {
+    if format_for_path(path, pretty).is_none() {
+        return Err(format!("don't know how to write {}", path.display()));
+    }
+    level.save(path, pretty).map_err(|e| format!("couldn't write {}: {}", path.display(), e))
+}
+
+/// Merges every level into the first, which keeps its ids. Starting from the first
+/// level instead of an empty one keeps the bounds when every level has them.
+fn merge_all(levels: Vec<SerdeLevel>) -> SerdeLevel {
+    let mut levels = levels.into_iter();
+    let mut merged = levels.next().unwrap_or_else(SerdeLevel::new);
+    for level in levels {
+        merged.merge(level);
+    }
+    merged.sort();
+    merged
+}
+
+fn paths_or_all(paths: &[PathBuf]) -> Vec<PathBuf> {
+    match paths.is_empty() {
+        true => level_paths(),
+        false => paths.to_vec(),
+    }
+}
+
+fn print_stats(path: &Path, level: &SerdeLevel) {
+    let shapes = || level.shapes.iter().map(|shape| &shape.shape);
+
+    let total = level.shapes.len();
+    let rectangles = shapes().filter(|shape| matches!(shape.shape_type, ShapeType::Rectangle)).count();
+    let stickable = shapes().filter(|shape| shape.stickable).count();
+    let goals = shapes().filter(|shape| shape.goal).count();
+    let checkpoints = shapes().filter(|shape| shape.checkpoint.is_some()).count();
+    let logic = shapes().filter(|shape| shape.logic.is_some()).count();
+
+    let bounds = level.shapes.iter().map(|shape| shape.aabb())
+        .reduce(|a, b| LevelRect { min: a.min.min(b.min), max: a.max.max(b.max) });
+
+    println!("{}", path.display());
+    println!("  hash         {:016x}", level.content_hash());
+    println!("  shapes       {} ({} rectangles, {} ovals)", total, rectangles, total - rectangles);
+    println!("  stickable    {} ({:.0}%)", stickable, stickable as f32 / total.max(1) as f32 * 100.0);
+    println!("  goals        {}", goals);
+    println!("  checkpoints  {}", checkpoints);
+    println!("  collectibles {}", level.collectible_count());
+    println!("  logic        {} shapes, {} wires", logic, level.wires.len());
+    match bounds {
+        Some(bounds) => println!("  bounds       {} to {} ({} x {})", bounds.min, bounds.max, bounds.max.x - bounds.min.x, bounds.max.y - bounds.min.y),
+        None => println!("  bounds       empty"),
+    }
+}
+
+/// Runs a tooling command, returning the process exit code
+pub fn run_tool(command: Command) -> i32 {
+    let result = match command {
+        Command::Launch { .. } => Ok(()),
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        Command::Validate { paths } => {
+            let paths = paths_or_all(&paths);
+            let paths: Vec<&Path> = paths.iter().map(|path| path.as_path()).collect();
+            match report_levels(&paths) {
+                true => Ok(()),
+                false => return 1,
+            }
+        },
+        Command::Format { path, minify, output } => load(&path)
+            .and_then(|level| save(output.as_ref().unwrap_or(&path), &level, !minify)),
+        Command::Convert { input, output } => load(&input).and_then(|level| save(&output, &level, true)),
+        Command::Merge { inputs, output } => inputs.iter()
+            .map(|path| load(path))
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|levels| save(&output, &merge_all(levels), true)),
+        Command::Diff { before, after } => {
+            let diff = match load(&before).and_then(|before| load(&after).map(|after| diff_levels(&before, &after))) {
+                Ok(diff) => diff,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    return 2
+                },
+            };
+            // Exits like diff(1), 1 when the levels differ
+            print!("{}", diff);
+            match diff.is_empty() {
+                true => Ok(()),
+                false => return 1,
+            }
+        },
+        Command::Merge3 { base, ours, theirs, output } => [&base, &ours, &theirs].into_iter()
+            .map(|path| load(path))
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|levels| {
+                let outcome = merge_levels(&levels[0], &levels[1], &levels[2]);
+                save(output.as_ref().unwrap_or(&ours), &outcome.level, true)?;
+                match outcome.conflicts.is_empty() {
+                    true => Ok(()),
+                    false => Err(format!("{} conflict(s):\n  {}", outcome.conflicts.len(), outcome.conflicts.join("\n  "))),
+                }
+            }),
+        Command::Stats { paths } => paths_or_all(&paths).iter()
+            .try_for_each(|path| load(path).map(|level| print_stats(path, &level))),
+        Command::Migrate { paths } => {
+            let mut profile = SaveProfile::load(Box::new(FileStorage::new(SAVE_DIR)));
+            let migrated = migrate_levels(&paths_or_all(&paths), &mut profile);
+            println!("Migrated {} level(s)", migrated);
+            profile.save().map_err(|e| format!("couldn't write save data: {}", e))
+        },
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::prelude::*;
+
+    use crate::{editor::{components::{EditorShape, ShapeId}, serde::SerdeShape}, game::level::LevelCamera};
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        Command::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parses_subcommands_and_falls_back_to_launching() {
+        assert_eq!(parse(&[]), Ok(Command::Launch { level: None, edit: false }));
+        assert_eq!(parse(&["--replay", "run.bin"]), Ok(Command::Launch { level: None, edit: false }));
+        assert_eq!(parse(&["edit", "a.json"]), Ok(Command::Launch { level: Some("a.json".into()), edit: true }));
+        assert_eq!(
+            parse(&["fmt", "-o", "out.json", "a.json", "--minify"]),
+            Ok(Command::Format { path: "a.json".into(), minify: true, output: Some("out.json".into()) }),
+        );
+        assert!(parse(&["merge", "a.json", "b.json"]).is_err());
+        assert!(parse(&["frobnicate"]).is_err());
+
+        assert_eq!(parse(&["--validate"]), Ok(Command::Validate { paths: Vec::new() }));
+        assert_eq!(parse(&["--validate", "a.json", "b.json"]), Ok(Command::Validate { paths: vec!["a.json".into(), "b.json".into()] }));
+    }
+
+    #[test]
+    fn merging_keeps_bounds_covering_every_level() {
+        let bounded = |x: f32| {
+            let mut level = SerdeLevel::new();
+            level.push(SerdeShape::new(EditorShape::new(ShapeType::Rectangle, true), &Transform::from_xyz(x, 0.0, 0.0)));
+            level.camera = LevelCamera { bounds: Some(LevelRect { min: Vec2::new(x - 10.0, -10.0), max: Vec2::new(x + 10.0, 10.0) }), zones: Vec::new() };
+            level.assign_shape_ids();
+            level
+        };
+
+        let merged = merge_all(vec![bounded(0.0), bounded(100.0)]);
+        assert_eq!(merged.camera.bounds, Some(LevelRect { min: Vec2::new(-10.0, -10.0), max: Vec2::new(110.0, 10.0) }));
+        // The first level keeps its ids
+        assert_eq!(merged.shapes[0].id, ShapeId(1));
+        assert_eq!(merged.shapes.len(), 2);
+    }
+}
@@ -0,0 +1,115 @@
+use bevy::{prelude::*, window::WindowMode, render::camera::ScalingMode};
+use serde::{Serialize, Deserialize};
+
+/// World units from the bottom of the screen to the top at zoom 1. The width
+/// follows the aspect ratio, so every resolution sees the same height of level.
+pub const WORLD_HEIGHT: f32 = 1080.0;
+
+/// Window sizes offered in the settings menu
+pub const RESOLUTIONS: [(u32, u32); 5] = [
+    (1280, 720),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+    (3840, 2160),
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayMode {
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+impl DisplayMode {
+    pub fn next(self) -> Self {
+        match self {
+            DisplayMode::Windowed => DisplayMode::Borderless,
+            DisplayMode::Borderless => DisplayMode::Fullscreen,
+            DisplayMode::Fullscreen => DisplayMode::Windowed,
+        }
+    }
+
+    pub fn window_mode(self) -> WindowMode {
+        match self {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Borderless => WindowMode::BorderlessFullscreen,
+            // Changes the monitor's video mode to the chosen resolution
+            DisplayMode::Fullscreen => WindowMode::SizedFullscreen,
+        }
+    }
+}
+
+/// Window mode and size. Borderless always covers the whole monitor, so the
+/// resolution only matters for the other two.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub mode: DisplayMode,
+    pub resolution: (u32, u32),
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self { mode: DisplayMode::Borderless, resolution: (1920, 1080) }
+    }
+}
+
+impl DisplaySettings {
+    pub fn window_descriptor(&self) -> WindowDescriptor {
+        WindowDescriptor {
+            mode: self.mode.window_mode(),
+            width: self.resolution.0 as f32,
+            height: self.resolution.1 as f32,
+            ..default()
+        }
+    }
+
+    /// The next size in `RESOLUTIONS`, wrapping around. Sizes from outside the list go back to the first.
+    pub fn next_resolution(&self) -> (u32, u32) {
+        let index = RESOLUTIONS.iter().position(|&resolution| resolution == self.resolution);
+        match index {
+            Some(index) => RESOLUTIONS[(index + 1) % RESOLUTIONS.len()],
+            None => RESOLUTIONS[0],
+        }
+    }
+}
+
+pub struct DisplayPlugin;
+
+impl Plugin for DisplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(apply_display_settings);
+    }
+}
+
+/// Camera showing `WORLD_HEIGHT` units vertically whatever the window size
+pub fn world_camera() -> Camera2dBundle {
+    let mut camera = Camera2dBundle::default();
+    camera.projection.scaling_mode = ScalingMode::FixedVertical(WORLD_HEIGHT);
+    camera
+}
+
+pub fn apply_display_settings(
+    settings: Res<DisplaySettings>,
+    mut windows: ResMut<Windows>,
+) {
+    if !settings.is_changed() { return }
+
+    let window = match windows.get_primary_mut() {
+        Some(window) => window,
+        None => return,
+    };
+
+    let mode = settings.mode.window_mode();
+    if window.mode() != mode {
+        window.set_mode(mode);
+    }
+
+    if settings.mode != DisplayMode::Borderless {
+        let (width, height) = settings.resolution;
+        if window.requested_width() != width as f32 || window.requested_height() != height as f32 {
+            window.set_resolution(width as f32, height as f32);
+        }
+    }
+}
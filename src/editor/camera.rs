@@ -1,20 +1,20 @@
 use bevy::{prelude::*, input::mouse::MouseWheel};
 
-use crate::util::Cursor;
+use crate::{input::Action, util::{Cursor, MainCamera}};
 
 const MAX_ZOOM: f32 = 0.01;
 
 pub fn camera_movement(
-    mut c_query: Query<(&mut Transform, &mut OrthographicProjection)>,
+    mut c_query: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
 
     cursor: Res<Cursor>,
-    mouse_input: Res<Input<MouseButton>>,
+    actions: Res<Input<Action>>,
     mut mouse_wheel: EventReader<MouseWheel>,
 ) {
     if c_query.is_empty() { return }
     let (mut transform, mut projection) = c_query.single_mut();
 
-    if mouse_input.pressed(MouseButton::Middle) && !mouse_input.just_pressed(MouseButton::Middle) {
+    if actions.pressed(Action::Pan) && !actions.just_pressed(Action::Pan) {
         transform.translation += cursor.delta.extend(0.0);
     }
 
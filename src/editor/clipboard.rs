@@ -0,0 +1,145 @@
+use bevy::{prelude::*, math::Vec3Swizzles, utils::HashMap};
+
+use crate::{input::Action, util::{Cursor, EntityQuery}, game::{logic::{Wire, LevelWires}, collectible::Collectible}};
+
+use super::{components::*, editor::SpawnShapeParam, serde::{SerdeShape, SerdeCollectible}};
+
+/// Shapes and collectibles copied in the editor, placed relative to the center of the
+/// copied selection, with the wires between the shapes
+#[derive(Default)]
+pub struct Clipboard {
+    shapes: Vec<SerdeShape>,
+    collectibles: Vec<SerdeCollectible>,
+    wires: Vec<Wire>,
+}
+
+/// Gives `shapes` new ids from `new_id`, returning what each old id became so
+/// references between the shapes can follow them
+pub fn remap_shape_ids(shapes: &mut [SerdeShape], mut new_id: impl FnMut() -> ShapeId) -> HashMap<ShapeId, ShapeId> {
+    shapes.iter_mut().map(|shape| {
+        let old = shape.id;
+        shape.id = new_id();
+        (old, shape.id)
+    }).collect()
+}
+
+pub fn copy_selection(
+    actions: Res<Input<Action>>,
+    mut clipboard: ResMut<Clipboard>,
+    level_wires: Res<LevelWires>,
+    selected_q: Query<(&ShapeId, &EditorShape, &Transform), With<Selected>>,
+    selected_collectible_q: Query<(&Collectible, &Transform), With<Selected>>,
+) {
+    let count = selected_q.iter().len() + selected_collectible_q.iter().len();
+    if !actions.just_pressed(Action::Copy) || count == 0 { return }
+
+    let center = selected_q.iter().map(|(_, _, transform)| transform)
+        .chain(selected_collectible_q.iter().map(|(_, transform)| transform))
+        .fold(Vec2::ZERO, |sum, transform| sum + transform.translation.xy()) / count as f32;
+
+    clipboard.shapes = selected_q.iter().map(|(id, editor_shape, transform)| SerdeShape {
+        id: *id,
+        translation: transform.translation.xy() - center,
+        ..SerdeShape::new(editor_shape.clone(), transform)
+    }).collect();
+    clipboard.shapes.sort_by_key(|shape| shape.id);
+
+    clipboard.collectibles = selected_collectible_q.iter().map(|(collectible, transform)| SerdeCollectible {
+        id: collectible.id,
+        translation: transform.translation.xy() - center,
+    }).collect();
+    clipboard.collectibles.sort_by_key(|collectible| collectible.id);
+
+    let copied = |id| clipboard.shapes.iter().any(|shape| shape.id == id);
+    clipboard.wires = level_wires.iter().filter(|wire| copied(wire.from) && copied(wire.to)).copied().collect();
+    info!("Copied {} shapes and {} collectibles", clipboard.shapes.len(), clipboard.collectibles.len());
+}
+
+/// Places the copied shapes and collectibles around the cursor with fresh ids,
+/// selecting them instead of what was selected
+pub fn paste_clipboard(
+    mut commands: Commands,
+    actions: Res<Input<Action>>,
+    cursor: Res<Cursor>,
+    clipboard: Res<Clipboard>,
+    mut level_wires: ResMut<LevelWires>,
+    selected: EntityQuery<Selected>,
+
+    mut spawn_shape_param: SpawnShapeParam,
+) {
+    if !actions.just_pressed(Action::Paste) || (clipboard.shapes.is_empty() && clipboard.collectibles.is_empty()) { return }
+
+    for entity in selected.iter() {
+        commands.entity(entity).remove::<Selected>();
+    }
+
+    let mut shapes = clipboard.shapes.clone();
+    let remap = remap_shape_ids(&mut shapes, || spawn_shape_param.new_shape_id());
+    level_wires.extend(clipboard.wires.iter().map(|wire| Wire { from: remap[&wire.from], to: remap[&wire.to], ..*wire }));
+
+    for shape in shapes {
+        let shape = SerdeShape { translation: shape.translation + cursor.world_pos, ..shape };
+        let entity = shape.spawn(&mut commands, &mut spawn_shape_param);
+        commands.entity(entity).insert(Selected);
+    }
+
+    let first_id = spawn_shape_param.next_collectible_id();
+    for (collectible, id) in clipboard.collectibles.iter().zip(first_id..) {
+        let collectible = SerdeCollectible { id, translation: collectible.translation + cursor.world_pos };
+        let entity = collectible.spawn(&mut commands, &mut spawn_shape_param);
+        commands.entity(entity).insert(Selected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{headless::{headless_app, run_frames, InputScript, ScriptAction}, editor::editor::EditorPlugin};
+
+    #[test]
+    fn remapping_gives_each_shape_a_new_id() {
+        let shape = |id| SerdeShape { id: ShapeId(id), ..SerdeShape::new(EditorShape::new(ShapeType::Oval, true), &Transform::default()) };
+        let mut shapes = vec![shape(3), shape(7)];
+
+        let mut next = 10;
+        let remap = remap_shape_ids(&mut shapes, || {
+            next += 1;
+            ShapeId(next - 1)
+        });
+
+        assert_eq!(shapes.iter().map(|shape| shape.id).collect::<Vec<_>>(), vec![ShapeId(10), ShapeId(11)]);
+        assert_eq!(remap.get(&ShapeId(7)), Some(&ShapeId(11)));
+    }
+
+    #[test]
+    fn collectibles_are_copied_pasted_and_deleted() {
+        let tap = |script: InputScript, frame, key| script
+            .at(frame, ScriptAction::PressKey(key))
+            .at(frame + 1, ScriptAction::ReleaseKey(key));
+        let mut script = InputScript::new().at(5, ScriptAction::Aim(Vec2::new(100.0, 100.0)));
+        script = tap(script, 5, KeyCode::O);
+        script = tap(script, 12, KeyCode::Y);
+        script = tap(script.at(14, ScriptAction::Aim(Vec2::new(300.0, 100.0))), 14, KeyCode::U);
+        script = tap(script, 22, KeyCode::Delete);
+
+        let mut app = headless_app(script);
+        app.add_plugin(EditorPlugin);
+        run_frames(&mut app, 10);
+
+        let original = app.world.query_filtered::<Entity, With<Collectible>>().single(&app.world);
+        app.world.entity_mut(original).insert(Selected);
+        run_frames(&mut app, 10);
+
+        let mut collectibles = app.world.query::<(&Collectible, &Transform, Option<&Selected>)>();
+        let mut pasted: Vec<_> = collectibles.iter(&app.world)
+            .map(|(collectible, transform, selected)| (collectible.id, transform.translation.xy(), selected.is_some()))
+            .collect();
+        pasted.sort_by_key(|(id, _, _)| *id);
+        assert_eq!(pasted, [(0, Vec2::new(100.0, 100.0), false), (1, Vec2::new(300.0, 100.0), true)]);
+
+        run_frames(&mut app, 5);
+        let left: Vec<u32> = collectibles.iter(&app.world).map(|(collectible, _, _)| collectible.id).collect();
+        assert_eq!(left, [0]);
+    }
+}
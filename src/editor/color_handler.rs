@@ -2,10 +2,13 @@ use bevy::sprite::ColorMaterial;
 
 use bevy::prelude::*;
 
-use crate::{util::{EntityQuery, update_color_material, ColorUpdate, ZipAll, ZipAllTrait, PreloadedAssets}, constants};
+use crate::{util::{EntityQuery, update_color_material, ColorUpdate, ZipAll, ZipAllTrait, PreloadedAssets}, constants, game::collectible::{Collectible, CollectibleMaterials}};
 
 use super::components::*;
 
+// Collectibles have no shape, deleted entities neither
+type ColoredQuery<'w, 's> = Query<'w, 's, Option<&'static EditorShape>, Or<(With<EditorShape>, With<Collectible>)>>;
+
 #[derive(Clone, Copy)]
 pub enum ColorStateChange {
     AddHover,
@@ -26,12 +29,13 @@ pub fn color_handler(
 
     changed_stickable: Query<Entity, Changed<EditorShape>>,
 
-    editor_shape_query: Query<&EditorShape>,
+    editor_shape_query: ColoredQuery,
 
     selected: EntityQuery<Selected>,
     hovered: EntityQuery<Hovered>,
 
     preload: Res<PreloadedAssets>,
+    collectible_materials: Res<CollectibleMaterials>,
 ) {
     let mut updated_entities = Vec::with_capacity(
         added_selected.iter().size_hint().1.unwrap_or(0) + 
@@ -56,12 +60,22 @@ pub fn color_handler(
             ColorStateChange::ChangeSticky => (None, None),
         };
 
-        let update = ColorUpdate {
-            selected: sel.unwrap_or(selected.contains(entity)),
-            hovered: hov.unwrap_or(hovered.contains(entity)),
-            stickable: editor_shape_query.get(entity).unwrap().stickable,
+        let (selected, hovered) = (sel.unwrap_or(selected.contains(entity)), hov.unwrap_or(hovered.contains(entity)));
+        let material = match editor_shape_query.get(entity) {
+            Ok(Some(editor_shape)) => preload.get_bw_color_handle(ColorUpdate {
+                selected,
+                hovered,
+                stickable: editor_shape.stickable,
+                goal: editor_shape.goal,
+                checkpoint: editor_shape.checkpoint.is_some(),
+            }.get_color()).clone(),
+            Ok(None) => match selected || hovered {
+                true => collectible_materials.highlighted.clone(),
+                false => collectible_materials.fresh.clone(),
+            },
+            Err(_) => continue,
         };
 
-        commands.entity(entity).insert(preload.get_bw_color_handle(update.get_color()).clone());
+        commands.entity(entity).insert(material);
     }
 }
\ No newline at end of file
@@ -6,12 +6,94 @@ use crate::util::ColorUpdate;
 
 use super::editor::SpawnShapeParam;
 
-#[derive(Serialize, Deserialize, Component, Clone, Debug)]
+#[derive(Serialize, Deserialize, Component, Clone, Debug, PartialEq)]
 pub enum ShapeType {
     Rectangle,
     Oval,
 }
 
+/// Identifies a shape across saves, so edits to a level can be diffed and merged.
+/// Unique within the level, 0 until one is given out.
+#[derive(Serialize, Deserialize, Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct ShapeId(pub u32);
+
+impl ShapeId {
+    pub fn is_unset(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Id the next shape created in the editor gets, moved past every id in a level when it loads
+#[derive(Deref, DerefMut)]
+pub struct NextShapeId(pub ShapeId);
+
+impl Default for NextShapeId {
+    fn default() -> Self {
+        Self(ShapeId(1))
+    }
+}
+
+/// Part a shape plays in the level's logic. Triggers and switches send signals
+/// down the level's wires, the rest act on them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ShapeLogic {
+    // On while the body is inside, doesn't collide with anything
+    Trigger,
+    // Flips on or off whenever a web sticks to it
+    Switch,
+    // Solid while off, open while on
+    Door,
+    // Goes out to `offset` from where it was placed and back every `period` seconds while on
+    Platform { offset: Vec2, period: f32 },
+    // Kills the body on touch while off, so wiring a switch to it makes it safe
+    Hazard,
+}
+
+impl ShapeLogic {
+    pub const DEFAULT_PLATFORM: ShapeLogic = ShapeLogic::Platform { offset: Vec2::new(0.0, 200.0), period: 3.0 };
+
+    /// Whether this sends signals instead of receiving them
+    pub fn is_source(&self) -> bool {
+        matches!(self, ShapeLogic::Trigger | ShapeLogic::Switch)
+    }
+
+    /// Next role when cycling through them in the editor
+    pub fn cycled(logic: Option<ShapeLogic>) -> Option<ShapeLogic> {
+        match logic {
+            None => Some(ShapeLogic::Trigger),
+            Some(ShapeLogic::Trigger) => Some(ShapeLogic::Switch),
+            Some(ShapeLogic::Switch) => Some(ShapeLogic::Door),
+            Some(ShapeLogic::Door) => Some(ShapeLogic::DEFAULT_PLATFORM),
+            Some(ShapeLogic::Platform { .. }) => Some(ShapeLogic::Hazard),
+            Some(ShapeLogic::Hazard) => None,
+        }
+    }
+}
+
+/// Groups a shape collides in. Webs only hit the first group, so triggers get their own
+/// that only the body overlaps.
+pub fn shape_collision_groups(logic: Option<ShapeLogic>) -> CollisionGroups {
+    match logic {
+        Some(ShapeLogic::Trigger) => CollisionGroups::new(
+            Group::from_bits_truncate(0b100),
+            Group::from_bits_truncate(0b10)
+        ),
+        _ => CollisionGroups::new(
+            Group::from_bits_truncate(0b1), 
+            Group::from_bits_truncate(0b11111110)
+        ),
+    }
+}
+
+/// Platforms are moved by setting their transform, everything else stays put
+pub fn shape_rigid_body(logic: Option<ShapeLogic>) -> RigidBody {
+    match logic {
+        Some(ShapeLogic::Platform { .. }) => RigidBody::KinematicPositionBased,
+        _ => RigidBody::Fixed,
+    }
+}
+
 #[derive(Component)]
 pub struct EditorSelectBox {
     pub start: Vec2
@@ -23,10 +105,22 @@ impl Default for EditorSelectBox {
     }
 }
 
-#[derive(Serialize, Deserialize, Component, Clone, Debug)]
+#[derive(Serialize, Deserialize, Component, Clone, Debug, PartialEq)]
 pub struct EditorShape {
     pub shape_type: ShapeType,
     pub stickable: bool,
+    // Webs stuck to this shape let go after this many seconds
+    #[serde(default)]
+    pub release_after: Option<f32>,
+    // Touching this shape finishes the run
+    #[serde(default)]
+    pub goal: bool,
+    // Touching this shape records a split, numbered in the order they were placed
+    #[serde(default)]
+    pub checkpoint: Option<u32>,
+    // Left out when unset so levels without logic keep their hash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logic: Option<ShapeLogic>,
 }
 
 #[derive(Component)]
@@ -45,15 +139,21 @@ impl EditorShape {
     pub fn new(shape_type: ShapeType, stickable: bool) -> Self {
         Self {
             shape_type,
-            stickable
+            stickable,
+            release_after: None,
+            goal: false,
+            checkpoint: None,
+            logic: None,
         }
     }
 
-    pub fn spawn(self, commands: &mut Commands, param: &mut SpawnShapeParam, transform: &Transform) {
+    pub fn spawn(self, commands: &mut Commands, param: &mut SpawnShapeParam, id: ShapeId, transform: &Transform) -> Entity {
         let color = ColorUpdate {
             selected: false,
             hovered: false,
             stickable: self.stickable,
+            goal: self.goal,
+            checkpoint: self.checkpoint.is_some(),
         }.get_color();
 
         let (collider, mesh_bundle) = match self.shape_type {
@@ -77,20 +177,24 @@ impl EditorShape {
             )},
         };
 
+        let is_trigger = self.logic == Some(ShapeLogic::Trigger);
         let child = commands.spawn_bundle(mesh_bundle)
             .insert_bundle((
                 collider,
-                RigidBody::Fixed,
+                shape_rigid_body(self.logic),
                 Friction::coefficient(0.1),
                 Restitution::coefficient(0.4),
-                CollisionGroups::new(
-                    Group::from_bits_truncate(0b1), 
-                    Group::from_bits_truncate(0b11111110)
-                ),
+                shape_collision_groups(self.logic),
                 self,
+                id,
                 Selectable,
             )).id();
 
+        if is_trigger {
+            commands.entity(child).insert(Sensor);
+        }
+
         commands.entity(param.level.single()).add_child(child);
+        child
     }
 }
\ No newline at end of file
@@ -0,0 +1,273 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::game::logic::Wire;
+
+use super::{components::ShapeId, serde::{SerdeLevel, SerdeShape, SerdeCollectible}};
+
+/// What happened to one shape between two versions of a level, matched by id
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShapeChange {
+    Added(SerdeShape),
+    Removed(SerdeShape),
+    Changed { before: SerdeShape, after: SerdeShape },
+}
+
+impl fmt::Display for ShapeChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (before, after) = match self {
+            ShapeChange::Added(shape) => return write!(f, "+ shape {}: {:?} at {}", shape.id.0, shape.shape.shape_type, shape.translation),
+            ShapeChange::Removed(shape) => return write!(f, "- shape {}: {:?} at {}", shape.id.0, shape.shape.shape_type, shape.translation),
+            ShapeChange::Changed { before, after } => (before, after),
+        };
+
+        let mut parts = Vec::new();
+        if before.translation != after.translation {
+            parts.push(format!("moved {} -> {}", before.translation, after.translation));
+        }
+        if before.angle != after.angle {
+            parts.push(format!("rotated {:.1} -> {:.1} degrees", before.angle.to_degrees(), after.angle.to_degrees()));
+        }
+        if before.scale != after.scale {
+            parts.push(format!("resized {} -> {}", before.scale, after.scale));
+        }
+        if before.shape != after.shape {
+            parts.push("properties changed".to_string());
+        }
+        write!(f, "~ shape {}: {}", after.id.0, parts.join(", "))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LevelDiff {
+    // In id order
+    pub shapes: Vec<ShapeChange>,
+    pub camera_changed: bool,
+    pub collectibles_changed: bool,
+    pub wires_changed: bool,
+}
+
+impl LevelDiff {
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty() && !self.camera_changed && !self.collectibles_changed && !self.wires_changed
+    }
+}
+
+impl fmt::Display for LevelDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in self.shapes.iter() {
+            writeln!(f, "{}", change)?;
+        }
+        if self.camera_changed {
+            writeln!(f, "~ camera")?;
+        }
+        if self.collectibles_changed {
+            writeln!(f, "~ collectibles")?;
+        }
+        if self.wires_changed {
+            writeln!(f, "~ wires")?;
+        }
+        Ok(())
+    }
+}
+
+fn shapes_by_id(level: &SerdeLevel) -> BTreeMap<ShapeId, &SerdeShape> {
+    level.shapes.iter().map(|shape| (shape.id, shape)).collect()
+}
+
+pub fn diff_levels(before: &SerdeLevel, after: &SerdeLevel) -> LevelDiff {
+    let (before_shapes, after_shapes) = (shapes_by_id(before), shapes_by_id(after));
+    let mut ids: Vec<ShapeId> = before_shapes.keys().chain(after_shapes.keys()).copied().collect();
+    ids.sort();
+    ids.dedup();
+
+    let shapes = ids.into_iter().filter_map(|id| match (before_shapes.get(&id), after_shapes.get(&id)) {
+        (Some(&before), Some(&after)) if before == after => None,
+        (Some(&before), Some(&after)) => Some(ShapeChange::Changed { before: before.clone(), after: after.clone() }),
+        (Some(&before), None) => Some(ShapeChange::Removed(before.clone())),
+        (None, Some(&after)) => Some(ShapeChange::Added(after.clone())),
+        (None, None) => None,
+    }).collect();
+
+    LevelDiff {
+        shapes,
+        camera_changed: before.camera != after.camera,
+        collectibles_changed: before.collectibles != after.collectibles,
+        wires_changed: before.wires != after.wires,
+    }
+}
+
+/// Level from a three-way merge, with what couldn't be merged cleanly
+#[derive(Clone, Debug)]
+pub struct MergeOutcome {
+    pub level: SerdeLevel,
+    // One line per conflict. Conflicts keep our side, or whichever side still has the item.
+    pub conflicts: Vec<String>,
+}
+
+/// Picks one of three versions of a value, None when both sides changed it differently
+fn merge_value<T: PartialEq>(base: T, ours: T, theirs: T) -> Option<T> {
+    match (ours == theirs, ours == base, theirs == base) {
+        (true, _, _) | (_, false, true) => Some(ours),
+        (_, true, false) => Some(theirs),
+        _ => None,
+    }
+}
+
+/// Merges lists of items with ids. Items both sides added under the same id are
+/// returned separately, since theirs only needs a new id.
+fn merge_items<T: Clone + PartialEq>(
+    base: &[T],
+    ours: &[T],
+    theirs: &[T],
+    id: impl Fn(&T) -> u32,
+    what: &str,
+    conflicts: &mut Vec<String>,
+) -> (Vec<T>, Vec<T>) {
+    let by_id = |items: &[T]| items.iter().map(|item| (id(item), item.clone())).collect::<BTreeMap<_, _>>();
+    let (mut base, mut ours, mut theirs) = (by_id(base), by_id(ours), by_id(theirs));
+
+    let mut ids: Vec<u32> = base.keys().chain(ours.keys()).chain(theirs.keys()).copied().collect();
+    ids.sort_unstable();
+    ids.dedup();
+
+    let mut merged = Vec::new();
+    let mut renumber = Vec::new();
+    for i in ids {
+        let (b, o, t) = (base.remove(&i), ours.remove(&i), theirs.remove(&i));
+        let item = match (b, o, t) {
+            (None, Some(o), Some(t)) if o != t => {
+                renumber.push(t);
+                Some(o)
+            },
+            (b, o, t) => match merge_value(b.as_ref(), o.as_ref(), t.as_ref()) {
+                Some(item) => item.cloned(),
+                None => {
+                    let (kept, message) = match (&o, &t) {
+                        (Some(_), Some(_)) => (&o, "changed on both sides, kept ours"),
+                        (None, _) => (&t, "removed by us but changed by them, kept theirs"),
+                        (_, None) => (&o, "changed by us but removed by them, kept ours"),
+                    };
+                    conflicts.push(format!("{} {}: {}", what, i, message));
+                    kept.clone()
+                },
+            },
+        };
+        merged.extend(item);
+    }
+
+    (merged, renumber)
+}
+
+/// Merges wires as sets: a wire is kept if both sides have it or one side added it.
+/// Wires to shapes that didn't make it into the merge are dropped.
+fn merge_wires(base: &[Wire], ours: &[Wire], theirs: &[Wire], shapes: &[SerdeShape], conflicts: &mut Vec<String>) -> Vec<Wire> {
+    let mut wires: Vec<Wire> = ours.iter().chain(theirs).copied()
+        .filter(|wire| {
+            let (b, o, t) = (base.contains(wire), ours.contains(wire), theirs.contains(wire));
+            (o && t) || (o && !b) || (t && !b)
+        })
+        .collect();
+    wires.sort();
+    wires.dedup();
+
+    wires.retain(|wire| {
+        let connected = [wire.from, wire.to].iter().all(|id| shapes.iter().any(|shape| shape.id == *id));
+        if !connected {
+            conflicts.push(format!("wire {} -> {}: a shape it connects was removed, dropped", wire.from.0, wire.to.0));
+        }
+        connected
+    });
+    wires
+}
+
+/// Three-way merge of two edited copies of `base`. Edits to different shapes,
+/// collectibles, wires or the camera combine, shapes both sides added are all kept.
+pub fn merge_levels(base: &SerdeLevel, ours: &SerdeLevel, theirs: &SerdeLevel) -> MergeOutcome {
+    let mut conflicts = Vec::new();
+
+    let (mut shapes, added_shapes) = merge_items(&base.shapes, &ours.shapes, &theirs.shapes, |shape| shape.id.0, "shape", &mut conflicts);
+    let (mut collectibles, added_collectibles) = merge_items(&base.collectibles, &ours.collectibles, &theirs.collectibles, |c| c.id, "collectible", &mut conflicts);
+
+    let camera = merge_value(&base.camera, &ours.camera, &theirs.camera).unwrap_or_else(|| {
+        conflicts.push("camera: changed on both sides, kept ours".to_string());
+        &ours.camera
+    }).clone();
+
+    // Their additions that clashed with ours move past every id, taking their wires along
+    let mut renumbered = BTreeMap::new();
+    let first_id = shapes.iter().map(|shape| shape.id.0 + 1).max().unwrap_or(1);
+    for (shape, id) in added_shapes.into_iter().zip(first_id..) {
+        renumbered.insert(shape.id, ShapeId(id));
+        shapes.push(SerdeShape { id: ShapeId(id), ..shape });
+    }
+    let renumber = |id: ShapeId| renumbered.get(&id).copied().unwrap_or(id);
+    let their_wires: Vec<Wire> = theirs.wires.iter()
+        .map(|wire| Wire { from: renumber(wire.from), to: renumber(wire.to), ..*wire })
+        .collect();
+    let wires = merge_wires(&base.wires, &ours.wires, &their_wires, &shapes, &mut conflicts);
+
+    let first_collectible = collectibles.iter().map(|c| c.id + 1).max().unwrap_or(0);
+    collectibles.extend(added_collectibles.into_iter().zip(first_collectible..)
+        .map(|(collectible, id)| SerdeCollectible { id, ..collectible }));
+
+    let mut level = SerdeLevel { shapes, camera, collectibles, wires };
+    level.sort();
+
+    MergeOutcome { level, conflicts }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use super::*;
+
+    use crate::editor::components::{EditorShape, ShapeType};
+
+    fn shape(id: u32, x: f32) -> SerdeShape {
+        SerdeShape {
+            id: ShapeId(id),
+            ..SerdeShape::new(EditorShape::new(ShapeType::Rectangle, true), &Transform::from_xyz(x, 0.0, 0.0).with_scale(Vec3::new(10.0, 10.0, 1.0)))
+        }
+    }
+
+    fn level(shapes: Vec<SerdeShape>) -> SerdeLevel {
+        SerdeLevel { shapes, ..SerdeLevel::new() }
+    }
+
+    #[test]
+    fn diff_reports_added_removed_and_moved() {
+        let before = level(vec![shape(1, 0.0), shape(2, 10.0), shape(3, 20.0)]);
+        let after = level(vec![shape(1, 0.0), shape(3, 25.0), shape(4, 30.0)]);
+
+        let diff = diff_levels(&before, &after);
+        assert_eq!(diff.shapes, vec![
+            ShapeChange::Removed(shape(2, 10.0)),
+            ShapeChange::Changed { before: shape(3, 20.0), after: shape(3, 25.0) },
+            ShapeChange::Added(shape(4, 30.0)),
+        ]);
+        assert!(diff.to_string().contains("~ shape 3: moved"));
+        assert!(diff_levels(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn merge_combines_separate_edits_and_keeps_both_additions() {
+        let base = level(vec![shape(1, 0.0), shape(2, 10.0), shape(3, 20.0)]);
+        // We move 1 and add a shape, they delete 2 and add a different shape under the same id, wired to 3
+        let ours = level(vec![shape(1, 5.0), shape(2, 10.0), shape(3, 20.0), shape(4, 100.0)]);
+        let wire = Wire { from: ShapeId(3), to: ShapeId(4), invert: false };
+        let theirs = SerdeLevel { wires: vec![wire], ..level(vec![shape(1, 0.0), shape(3, 20.0), shape(4, 200.0)]) };
+
+        let outcome = merge_levels(&base, &ours, &theirs);
+        assert!(outcome.conflicts.is_empty(), "{:?}", outcome.conflicts);
+        assert_eq!(outcome.level.shapes, vec![shape(1, 5.0), shape(3, 20.0), shape(4, 100.0), shape(5, 200.0)]);
+        // Their wire follows their shape to its new id
+        assert_eq!(outcome.level.wires, vec![Wire { to: ShapeId(5), ..wire }]);
+
+        // Both moving the same shape is a conflict, ours wins
+        let theirs = level(vec![shape(1, -5.0), shape(2, 10.0), shape(3, 20.0)]);
+        let outcome = merge_levels(&base, &ours, &theirs);
+        assert_eq!(outcome.conflicts.len(), 1);
+        assert_eq!(outcome.level.shapes[0], shape(1, 5.0));
+    }
+}
@@ -4,30 +4,39 @@ use bevy::{prelude::*, ecs::system::SystemParam};
 use bevy_inspector_egui::egui::text_edit::TextEditOutput;
 use bevy_rapier2d::prelude::{Collider, Sensor, RapierContext};
 
-use crate::game::level::Level;
-use crate::game::player::spawn::{player_spawn, Respawn};
-use crate::util::{EntityQuery, Cursor, cursor_pos, PreloadedAssets};
+use crate::game::{level::{Level, index_shapes}, collectible::{Collectible, CollectibleMaterials, spawn_collectible}, logic::LevelWires};
+use crate::game::player::{camera::FollowCamera, spawn::Respawn};
+use crate::input::Action;
+use crate::util::{EntityQuery, Cursor, MainCamera, cursor_pos, PreloadedAssets};
 
 use super::camera::camera_movement;
+use super::clipboard::{Clipboard, copy_selection, paste_clipboard};
 use super::color_handler::color_handler;
 use super::components::*;
 use super::hover::hover_shapes;
 use super::selection::selection_manipulation;
+use super::wiring::{cycle_wires, draw_wires};
 use super::serde::*;
 
 pub struct EditorPlugin;
 
 impl Plugin for EditorPlugin {
     fn build(&self, app: &mut App) {
+        // Loading the level and spawning the player come from the game plugin
         app.add_startup_system(editor_startup)
-            .insert_resource(Respawn(true))
-            .add_system(player_spawn)
+            .add_startup_system_to_stage(StartupStage::PostStartup, stop_following)
             .add_system_to_stage(CoreStage::PreUpdate, camera_movement.after(cursor_pos))
             // Definitely off by one, but who cares
             .add_system(selection_manipulation)
-            .add_system(editor_load)
             .add_system(hover_shapes)
             .add_system(editor.after(hover_shapes))
+            .add_system(editor_spawn_collectible)
+            .add_system(delete_selection.after(selection_manipulation))
+            .init_resource::<Clipboard>()
+            .add_system(copy_selection)
+            .add_system(paste_clipboard.after(copy_selection))
+            .add_system(cycle_wires.after(hover_shapes))
+            .add_system_to_stage(CoreStage::PostUpdate, draw_wires.after(index_shapes))
             .add_system_to_stage(CoreStage::PostUpdate, color_handler)
             .add_system_to_stage(CoreStage::PostUpdate, editor_save);
     }
@@ -36,12 +45,30 @@ impl Plugin for EditorPlugin {
 #[derive(SystemParam)]
 pub struct SpawnShapeParam<'w, 's> {
     pub preload: Res<'w, PreloadedAssets>,
+    pub collectible_materials: Res<'w, CollectibleMaterials>,
     pub level: EntityQuery<'w, 's, Level>,
+    pub next_shape_id: ResMut<'w, NextShapeId>,
+    pub collectibles: Query<'w, 's, &'static Collectible>,
     
     #[system_param(ignore)]
     marker: PhantomData<&'s usize>,
 }
 
+impl<'w, 's> SpawnShapeParam<'w, 's> {
+    /// Hands out an id for a shape being created
+    pub fn new_shape_id(&mut self) -> ShapeId {
+        let id = **self.next_shape_id;
+        **self.next_shape_id = ShapeId(id.0 + 1);
+        id
+    }
+
+    /// First collectible id not used in the level. Ids are kept in the save, so
+    /// they're never reused while the collectible they belong to is around.
+    pub fn next_collectible_id(&self) -> u32 {
+        self.collectibles.iter().map(|c| c.id + 1).max().unwrap_or(0)
+    }
+}
+
 pub fn editor_startup(
     mut commands: Commands,
 
@@ -64,11 +91,20 @@ pub fn editor_startup(
     // });
 }
 
+/// The camera is panned by hand while editing
+pub fn stop_following(
+    mut commands: Commands,
+    camera_q: Query<Entity, (With<MainCamera>, With<FollowCamera>)>,
+) {
+    for entity in camera_q.iter() {
+        commands.entity(entity).remove::<FollowCamera>();
+    }
+}
+
 pub fn editor(
     mut commands: Commands,
 
-    mouse_button_input: Res<Input<MouseButton>>,
-    keyboard_input: Res<Input<KeyCode>>,
+    actions: Res<Input<Action>>,
     cursor: Res<Cursor>,
     rapier_context: Res<RapierContext>,
     mut respawn: ResMut<Respawn>,
@@ -82,40 +118,52 @@ pub fn editor(
 
     mut spawn_shape_param: SpawnShapeParam,
 ) {
-    if keyboard_input.just_pressed(KeyCode::R) {
+    if actions.just_pressed(Action::SpawnRectangle) {
         let shape = EditorShape {
             shape_type: ShapeType::Rectangle,
             stickable: true,
+            release_after: None,
+            goal: false,
+            checkpoint: None,
+            logic: None,
         };
 
+        let id = spawn_shape_param.new_shape_id();
         shape.spawn(
             &mut commands, 
             &mut spawn_shape_param,
+            id,
             &Transform::from_translation((cursor.world_pos).extend(1.0))
                 .with_scale(Vec3::new(20.0, 20.0, 1.0))
         );
     }
 
-    if keyboard_input.just_pressed(KeyCode::C) {
+    if actions.just_pressed(Action::SpawnOval) {
         let shape = EditorShape {
             shape_type: ShapeType::Oval,
-            stickable: true
+            stickable: true,
+            release_after: None,
+            goal: false,
+            checkpoint: None,
+            logic: None,
         };
 
+        let id = spawn_shape_param.new_shape_id();
         shape.spawn(
             &mut commands, 
             &mut spawn_shape_param,
+            id,
             &Transform::from_translation((cursor.world_pos).extend(1.0))
                 .with_scale(Vec3::new(20.0, 20.0, 1.0))
         );
     }
 
-    if keyboard_input.just_pressed(KeyCode::K) {
+    if actions.just_pressed(Action::RespawnPlayer) {
         **respawn = true;
     }
 
-    if mouse_button_input.just_pressed(MouseButton::Right) {
-        if !keyboard_input.pressed(KeyCode::LShift) {
+    if actions.just_pressed(Action::BoxSelect) {
+        if !actions.pressed(Action::Modifier) {
             for entity in selected.iter() {
                 commands.entity(entity).remove::<Selected>();
             }
@@ -127,7 +175,7 @@ pub fn editor(
         let mut transform = transform_query.get_mut(entity).unwrap();
         transform.scale = Vec3::new(0.0, 0.0, 1.0);
 
-    } else if mouse_button_input.pressed(MouseButton::Right) {
+    } else if actions.pressed(Action::BoxSelect) {
         let (entity, select_box) = select_box.single();
         let mut transform = transform_query.get_mut(entity).unwrap();
 
@@ -135,7 +183,7 @@ pub fn editor(
         transform.scale = (select_box.start - cursor.world_pos).abs().extend(1.0);
     }
 
-    if mouse_button_input.just_released(MouseButton::Right) {
+    if actions.just_released(Action::BoxSelect) {
         let (select_box_entity, box_select) = select_box.single();
         let select_box_size = (box_select.start - cursor.world_pos).abs();
 
@@ -157,4 +205,36 @@ pub fn editor(
             }
         }
     }
-}
\ No newline at end of file
+}
+
+pub fn editor_spawn_collectible(
+    mut commands: Commands,
+
+    actions: Res<Input<Action>>,
+    cursor: Res<Cursor>,
+
+    mut spawn_shape_param: SpawnShapeParam,
+) {
+    if !actions.just_pressed(Action::SpawnCollectible) { return }
+
+    let id = spawn_shape_param.next_collectible_id();
+    spawn_collectible(&mut commands, &mut spawn_shape_param, Collectible { id }, cursor.world_pos);
+}
+
+/// Removes the selected shapes and collectibles, along with every wire to or from the shapes
+pub fn delete_selection(
+    mut commands: Commands,
+
+    actions: Res<Input<Action>>,
+    mut wires: ResMut<LevelWires>,
+    selected_q: Query<(Entity, Option<&ShapeId>), With<Selected>>,
+) {
+    if !actions.just_pressed(Action::DeleteSelection) { return }
+
+    for (entity, id) in selected_q.iter() {
+        if let Some(id) = id {
+            wires.retain(|wire| wire.from != *id && wire.to != *id);
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,393 @@
+use std::{io, path::Path};
+
+use crate::{game::{level::{LevelCamera, LevelRect, CameraZone}, logic::Wire}, util::{ByteReader, push_vec2, invalid_data}};
+
+use super::{components::{EditorShape, ShapeType, ShapeId, ShapeLogic}, serde::{SerdeLevel, SerdeShape, SerdeCollectible}, migrate::LegacyLevel};
+
+pub const LEVEL_MAGIC: &[u8; 4] = b"SPLV";
+// Version 1 had no shape ids, version 2 no logic or wires
+pub const LEVEL_VERSION: u8 = 3;
+
+// Extensions of every format a level can be loaded from
+pub const LEVEL_EXTENSIONS: [&str; 3] = ["json", "ron", "lvl"];
+
+/// A way of storing levels on disk
+pub trait LevelFormat {
+    fn encode(&self, level: &SerdeLevel) -> io::Result<Vec<u8>>;
+    fn decode(&self, bytes: &[u8]) -> io::Result<SerdeLevel>;
+}
+
+pub struct JsonFormat {
+    pub pretty: bool,
+}
+
+impl LevelFormat for JsonFormat {
+    fn encode(&self, level: &SerdeLevel) -> io::Result<Vec<u8>> {
+        let result = match self.pretty {
+            true => serde_json::to_vec_pretty(level),
+            false => serde_json::to_vec(level),
+        };
+        result.map_err(|e| invalid_data(&e.to_string()))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> io::Result<SerdeLevel> {
+        serde_json::from_slice(bytes)
+            .or_else(|e| serde_json::from_slice::<LegacyLevel>(bytes).map(LegacyLevel::migrate).map_err(|_| e))
+            .map_err(|e| invalid_data(&e.to_string()))
+    }
+}
+
+pub struct RonFormat {
+    pub pretty: bool,
+}
+
+impl LevelFormat for RonFormat {
+    fn encode(&self, level: &SerdeLevel) -> io::Result<Vec<u8>> {
+        let result = match self.pretty {
+            true => ron::ser::to_string_pretty(level, ron::ser::PrettyConfig::new().struct_names(false)),
+            false => ron::to_string(level),
+        };
+        result.map(String::into_bytes).map_err(|e| invalid_data(&e.to_string()))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> io::Result<SerdeLevel> {
+        ron::de::from_bytes(bytes)
+            .or_else(|e| ron::de::from_bytes::<LegacyLevel>(bytes).map(LegacyLevel::migrate).map_err(|_| e))
+            .map_err(|e| invalid_data(&e.to_string()))
+    }
+}
+
+/// Layout: magic, version, then shapes, camera, collectibles and wires, each list
+/// prefixed with its length. All little endian.
+pub struct BinaryFormat;
+
+const STICKABLE: u8 = 1;
+const GOAL: u8 = 1 << 1;
+const HAS_RELEASE: u8 = 1 << 2;
+const HAS_CHECKPOINT: u8 = 1 << 3;
+const HAS_LOGIC: u8 = 1 << 4;
+
+fn push_f32(bytes: &mut Vec<u8>, value: f32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_len(bytes: &mut Vec<u8>, len: usize) {
+    bytes.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn push_rect(bytes: &mut Vec<u8>, rect: &LevelRect) {
+    push_vec2(bytes, rect.min);
+    push_vec2(bytes, rect.max);
+}
+
+fn read_rect(reader: &mut ByteReader) -> io::Result<LevelRect> {
+    Ok(LevelRect { min: reader.vec2()?, max: reader.vec2()? })
+}
+
+fn read_flag(reader: &mut ByteReader) -> io::Result<bool> {
+    Ok(reader.take(1)?[0] != 0)
+}
+
+impl BinaryFormat {
+    fn push_shape(bytes: &mut Vec<u8>, shape: &SerdeShape) {
+        bytes.extend_from_slice(&shape.id.0.to_le_bytes());
+
+        let editor_shape = &shape.shape;
+        bytes.push(match editor_shape.shape_type {
+            ShapeType::Rectangle => 0,
+            ShapeType::Oval => 1,
+        });
+
+        let mut flags = 0;
+        if editor_shape.stickable { flags |= STICKABLE }
+        if editor_shape.goal { flags |= GOAL }
+        if editor_shape.release_after.is_some() { flags |= HAS_RELEASE }
+        if editor_shape.checkpoint.is_some() { flags |= HAS_CHECKPOINT }
+        if editor_shape.logic.is_some() { flags |= HAS_LOGIC }
+        bytes.push(flags);
+
+        if let Some(release_after) = editor_shape.release_after {
+            push_f32(bytes, release_after);
+        }
+        if let Some(checkpoint) = editor_shape.checkpoint {
+            bytes.extend_from_slice(&checkpoint.to_le_bytes());
+        }
+        if let Some(logic) = editor_shape.logic {
+            Self::push_logic(bytes, logic);
+        }
+
+        push_vec2(bytes, shape.translation);
+        push_f32(bytes, shape.angle);
+        push_vec2(bytes, shape.scale);
+    }
+
+    fn read_shape(reader: &mut ByteReader, version: u8) -> io::Result<SerdeShape> {
+        let id = match version {
+            1 => ShapeId::default(),
+            _ => ShapeId(reader.u32()?),
+        };
+
+        let shape_type = match reader.take(1)?[0] {
+            0 => ShapeType::Rectangle,
+            1 => ShapeType::Oval,
+            other => return Err(invalid_data(&format!("unknown shape type {}", other))),
+        };
+
+        let flags = reader.take(1)?[0];
+        let release_after = match flags & HAS_RELEASE != 0 {
+            true => Some(reader.f32()?),
+            false => None,
+        };
+        let checkpoint = match flags & HAS_CHECKPOINT != 0 {
+            true => Some(reader.u32()?),
+            false => None,
+        };
+        let logic = match flags & HAS_LOGIC != 0 {
+            true => Some(Self::read_logic(reader)?),
+            false => None,
+        };
+
+        Ok(SerdeShape {
+            id,
+            shape: EditorShape {
+                shape_type,
+                stickable: flags & STICKABLE != 0,
+                release_after,
+                goal: flags & GOAL != 0,
+                checkpoint,
+                logic,
+            },
+            translation: reader.vec2()?,
+            angle: reader.f32()?,
+            scale: reader.vec2()?,
+        })
+    }
+
+    fn push_logic(bytes: &mut Vec<u8>, logic: ShapeLogic) {
+        match logic {
+            ShapeLogic::Trigger => bytes.push(0),
+            ShapeLogic::Switch => bytes.push(1),
+            ShapeLogic::Door => bytes.push(2),
+            ShapeLogic::Platform { offset, period } => {
+                bytes.push(3);
+                push_vec2(bytes, offset);
+                push_f32(bytes, period);
+            },
+            ShapeLogic::Hazard => bytes.push(4),
+        }
+    }
+
+    fn read_logic(reader: &mut ByteReader) -> io::Result<ShapeLogic> {
+        Ok(match reader.take(1)?[0] {
+            0 => ShapeLogic::Trigger,
+            1 => ShapeLogic::Switch,
+            2 => ShapeLogic::Door,
+            3 => ShapeLogic::Platform { offset: reader.vec2()?, period: reader.f32()? },
+            4 => ShapeLogic::Hazard,
+            other => return Err(invalid_data(&format!("unknown logic role {}", other))),
+        })
+    }
+
+    fn push_camera(bytes: &mut Vec<u8>, camera: &LevelCamera) {
+        match &camera.bounds {
+            Some(bounds) => {
+                bytes.push(1);
+                push_rect(bytes, bounds);
+            },
+            None => bytes.push(0),
+        }
+
+        push_len(bytes, camera.zones.len());
+        for zone in camera.zones.iter() {
+            push_rect(bytes, &zone.area);
+            match zone.focus {
+                Some(focus) => {
+                    bytes.push(1);
+                    push_vec2(bytes, focus);
+                },
+                None => bytes.push(0),
+            }
+            match zone.zoom {
+                Some(zoom) => {
+                    bytes.push(1);
+                    push_f32(bytes, zoom);
+                },
+                None => bytes.push(0),
+            }
+        }
+    }
+
+    fn read_camera(reader: &mut ByteReader) -> io::Result<LevelCamera> {
+        let bounds = match read_flag(reader)? {
+            true => Some(read_rect(reader)?),
+            false => None,
+        };
+
+        let num_zones = reader.u32()?;
+        let mut zones = Vec::new();
+        for _ in 0..num_zones {
+            let area = read_rect(reader)?;
+            let focus = match read_flag(reader)? {
+                true => Some(reader.vec2()?),
+                false => None,
+            };
+            let zoom = match read_flag(reader)? {
+                true => Some(reader.f32()?),
+                false => None,
+            };
+            zones.push(CameraZone { area, focus, zoom });
+        }
+
+        Ok(LevelCamera { bounds, zones })
+    }
+}
+
+impl LevelFormat for BinaryFormat {
+    fn encode(&self, level: &SerdeLevel) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(16 + level.shapes.len() * 30);
+        bytes.extend_from_slice(LEVEL_MAGIC);
+        bytes.push(LEVEL_VERSION);
+
+        push_len(&mut bytes, level.shapes.len());
+        for shape in level.shapes.iter() {
+            Self::push_shape(&mut bytes, shape);
+        }
+
+        Self::push_camera(&mut bytes, &level.camera);
+
+        push_len(&mut bytes, level.collectibles.len());
+        for collectible in level.collectibles.iter() {
+            bytes.extend_from_slice(&collectible.id.to_le_bytes());
+            push_vec2(&mut bytes, collectible.translation);
+        }
+
+        push_len(&mut bytes, level.wires.len());
+        for wire in level.wires.iter() {
+            bytes.extend_from_slice(&wire.from.0.to_le_bytes());
+            bytes.extend_from_slice(&wire.to.0.to_le_bytes());
+            bytes.push(wire.invert as u8);
+        }
+
+        Ok(bytes)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> io::Result<SerdeLevel> {
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.take(4)? != LEVEL_MAGIC {
+            return Err(invalid_data("not a binary level"));
+        }
+
+        let version = reader.take(1)?[0];
+        if version == 0 || version > LEVEL_VERSION {
+            return Err(invalid_data(&format!("unsupported level version {}", version)));
+        }
+
+        // Lengths aren't trusted for preallocating, a corrupt one would run out of memory
+        let num_shapes = reader.u32()?;
+        let mut shapes = Vec::new();
+        for _ in 0..num_shapes {
+            shapes.push(Self::read_shape(&mut reader, version)?);
+        }
+
+        let camera = Self::read_camera(&mut reader)?;
+
+        let num_collectibles = reader.u32()?;
+        let mut collectibles = Vec::new();
+        for _ in 0..num_collectibles {
+            collectibles.push(SerdeCollectible { id: reader.u32()?, translation: reader.vec2()? });
+        }
+
+        let mut wires = Vec::new();
+        if version >= 3 {
+            let num_wires = reader.u32()?;
+            for _ in 0..num_wires {
+                wires.push(Wire { from: ShapeId(reader.u32()?), to: ShapeId(reader.u32()?), invert: read_flag(&mut reader)? });
+            }
+        }
+
+        Ok(SerdeLevel { shapes, camera, collectibles, wires })
+    }
+}
+
+/// Format to write `path` in, from its extension. `pretty` only matters for text formats.
+pub fn format_for_path(path: &Path, pretty: bool) -> Option<Box<dyn LevelFormat>> {
+    match path.extension()?.to_str()? {
+        "json" => Some(Box::new(JsonFormat { pretty })),
+        "ron" => Some(Box::new(RonFormat { pretty })),
+        "lvl" => Some(Box::new(BinaryFormat)),
+        _ => None,
+    }
+}
+
+/// Format of a level file from its contents, so a renamed file still loads
+pub fn detect_format(bytes: &[u8]) -> Box<dyn LevelFormat> {
+    if bytes.starts_with(LEVEL_MAGIC) {
+        return Box::new(BinaryFormat);
+    }
+
+    // A JSON level is an object, a RON one a struct
+    match bytes.iter().find(|byte| !byte.is_ascii_whitespace()) {
+        Some(b'{') => Box::new(JsonFormat { pretty: true }),
+        _ => Box::new(RonFormat { pretty: true }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use super::*;
+
+    fn test_level() -> SerdeLevel {
+        let mut level = SerdeLevel::new();
+        let mut goal = EditorShape::new(ShapeType::Oval, false);
+        goal.goal = true;
+        goal.release_after = Some(1.5);
+        goal.checkpoint = Some(2);
+
+        level.push(SerdeShape::new(
+            EditorShape::new(ShapeType::Rectangle, true),
+            &Transform::from_xyz(-120.5, 300.25, 0.0).with_scale(Vec3::new(400.0, 20.0, 1.0)),
+        ));
+        level.push(SerdeShape::new(
+            goal,
+            &Transform::from_xyz(900.0, -40.0, 0.0)
+                .with_rotation(Quat::from_rotation_z(0.7))
+                .with_scale(Vec3::new(35.0, 60.0, 1.0)),
+        ));
+        level.camera = LevelCamera {
+            bounds: Some(LevelRect { min: Vec2::splat(-2000.0), max: Vec2::splat(2000.0) }),
+            zones: vec![CameraZone { area: LevelRect { min: Vec2::ZERO, max: Vec2::ONE * 500.0 }, focus: Some(Vec2::new(250.0, 250.0)), zoom: None }],
+        };
+        level.collectibles.push(SerdeCollectible { id: 3, translation: Vec2::new(10.0, 20.0) });
+        level.shapes[0].shape.logic = Some(ShapeLogic::Switch);
+        level.shapes[1].shape.logic = Some(ShapeLogic::Platform { offset: Vec2::new(-50.0, 75.5), period: 2.5 });
+        level.assign_shape_ids();
+        level.wires.push(Wire { from: ShapeId(1), to: ShapeId(2), invert: true });
+        level
+    }
+
+    fn round_trip(format: &dyn LevelFormat, level: &SerdeLevel) -> SerdeLevel {
+        let bytes = format.encode(level).unwrap();
+        detect_format(&bytes).decode(&bytes).unwrap()
+    }
+
+    #[test]
+    fn every_format_round_trips_exactly() {
+        let level = test_level();
+        for pretty in [true, false] {
+            assert_eq!(round_trip(&JsonFormat { pretty }, &level), level);
+            assert_eq!(round_trip(&RonFormat { pretty }, &level), level);
+        }
+        assert_eq!(round_trip(&BinaryFormat, &level), level);
+        assert_eq!(round_trip(&BinaryFormat, &level).content_hash(), level.content_hash());
+    }
+
+    #[test]
+    fn rejects_truncated_binary() {
+        let bytes = BinaryFormat.encode(&test_level()).unwrap();
+        assert!(BinaryFormat.decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(BinaryFormat.decode(b"SPLV\x09").is_err());
+    }
+}
@@ -2,7 +2,7 @@ use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 use bevy_rapier2d::rapier::geometry::Group;
 
-use crate::util::{Cursor, EntityQuery};
+use crate::{input::Action, util::{Cursor, EntityQuery}};
 
 use super::components::*;
 
@@ -10,20 +10,23 @@ pub fn hover_shapes(
     mut commands: Commands,
 
     cursor: Res<Cursor>,
-    mouse_button_input: Res<Input<MouseButton>>,
+    actions: Res<Input<Action>>,
     rapier_context: Res<RapierContext>,
 
     current_hovered_query: EntityQuery<Hovered>,
+    selectable: EntityQuery<Selectable>,
 ) {
-    if mouse_button_input.pressed(MouseButton::Left) { return }
+    if actions.pressed(Action::Select) { return }
 
     if cursor.moved {
         let mut last = None;
+        // Triggers and collectibles are sensors in their own groups, but still things to edit
+        let is_shape = |entity| selectable.contains(entity);
         rapier_context.intersections_with_point(
             cursor.world_pos, 
             QueryFilter::default().groups(
-                InteractionGroups::new(Group::ALL, Group::from_bits_truncate(0b1))
-            ).exclude_sensors(), 
+                InteractionGroups::new(Group::ALL, Group::from_bits_truncate(0b1101))
+            ).predicate(&is_shape), 
             |entity| {
                 last = Some(entity);
                 true
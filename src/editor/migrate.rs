@@ -0,0 +1,117 @@
+use std::{fs, io, path::Path};
+
+use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::{game::{ghost::GhostRun, level::LevelCamera}, save::SaveProfile};
+
+use super::{components::EditorShape, serde::{SerdeLevel, SerdeShape, SerdeCollectible, content_hash}};
+
+/// Shape as saved before rotations were stored as an angle
+#[derive(Serialize, Deserialize)]
+struct LegacyShape {
+    shape: EditorShape,
+    translation: Vec2,
+    rotation: Quat,
+    scale: Vec2,
+}
+
+/// Level as saved before rotations were stored as an angle. Serializes exactly
+/// like it used to, so its hash matches what save data was keyed by.
+#[derive(Serialize, Deserialize)]
+pub struct LegacyLevel {
+    shapes: Vec<LegacyShape>,
+    #[serde(default, skip_serializing_if = "LevelCamera::is_empty")]
+    camera: LevelCamera,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    collectibles: Vec<SerdeCollectible>,
+}
+
+impl LegacyLevel {
+    /// Parses a legacy JSON or RON level, None if it isn't one
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        serde_json::from_slice(bytes).ok()
+            .or_else(|| ron::de::from_bytes(bytes).ok())
+    }
+
+    /// The same level in the current model, with shape ids so wires can refer to them
+    pub fn migrate(self) -> SerdeLevel {
+        let shapes = self.shapes.into_iter().map(|legacy| SerdeShape::new(
+            legacy.shape,
+            &Transform::from_translation(legacy.translation.extend(0.0))
+                .with_rotation(legacy.rotation)
+                .with_scale(legacy.scale.extend(1.0)),
+        )).collect();
+
+        let mut level = SerdeLevel { shapes, camera: self.camera, collectibles: self.collectibles, wires: Vec::new() };
+        level.assign_shape_ids();
+        level
+    }
+}
+
+/// Rewrites a legacy level file in the current model, moving its times,
+/// collectibles and ghost over to the new hash. Returns whether it was legacy.
+pub fn migrate_level_file(path: &Path, profile: &mut SaveProfile) -> io::Result<bool> {
+    let legacy = match LegacyLevel::parse(&fs::read(path)?) {
+        Some(legacy) => legacy,
+        None => return Ok(false),
+    };
+
+    let old_hash = content_hash(&legacy);
+    let level = legacy.migrate();
+    let new_hash = level.content_hash();
+    level.save(path, true)?;
+
+    profile.data.rekey_level(old_hash, new_hash);
+    profile.mark_dirty();
+    if let Err(e) = GhostRun::rekey(old_hash, new_hash) {
+        warn!("Couldn't move the ghost of {}: {}", path.display(), e);
+    }
+
+    info!("Migrated {} ({:016x} -> {:016x})", path.display(), old_hash, new_hash);
+    Ok(true)
+}
+
+/// Migrates every level at `paths` that needs it, logging anything that fails
+pub fn migrate_levels<P: AsRef<Path>>(paths: &[P], profile: &mut SaveProfile) -> usize {
+    paths.iter().filter(|path| match migrate_level_file(path.as_ref(), profile) {
+        Ok(migrated) => migrated,
+        Err(e) => {
+            warn!("Couldn't migrate {}: {}", path.as_ref().display(), e);
+            false
+        },
+    }).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    use crate::editor::{format::detect_format, components::ShapeId};
+
+    const LEGACY: &str = r#"{"shapes":[
+        {"shape":{"shape_type":"Rectangle","stickable":true},"translation":[10.0,20.0],"rotation":[0.0,0.0,0.9238795,0.38268343],"scale":[-30.0,5.0]},
+        {"shape":{"shape_type":"Oval","stickable":false,"goal":true},"translation":[0.0,0.0],"rotation":[0.0,0.0,0.0,1.0],"scale":[8.0,8.0]}
+    ]}"#;
+
+    #[test]
+    fn legacy_levels_hash_as_before_and_load_normalized() {
+        let legacy = LegacyLevel::parse(LEGACY.as_bytes()).unwrap();
+        // What the game hashed this file to before the migration
+        assert_eq!(content_hash(&legacy), 0xffc1104af883116d);
+
+        let level = detect_format(LEGACY.as_bytes()).decode(LEGACY.as_bytes()).unwrap();
+        let shape = &level.shapes[0];
+        // 135 degrees is the same rectangle as -45
+        assert!((shape.angle + PI / 4.0).abs() < 1e-5, "{}", shape.angle);
+        assert_eq!(shape.scale, Vec2::new(30.0, 5.0));
+        assert_eq!(level.shapes[1].angle, 0.0);
+        assert!(level.shapes[1].shape.goal);
+        assert_eq!(level.shapes.iter().map(|shape| shape.id).collect::<Vec<_>>(), [ShapeId(1), ShapeId(2)]);
+
+        // Already migrated levels aren't legacy
+        assert!(LegacyLevel::parse(&serde_json::to_vec(&level).unwrap()).is_none());
+    }
+}
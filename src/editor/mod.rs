@@ -2,6 +2,12 @@ pub mod editor;
 pub mod components;
 pub mod hover;
 pub mod serde;
+pub mod format;
+pub mod migrate;
+pub mod diff;
+pub mod clipboard;
+pub mod wiring;
 pub mod color_handler;
 pub mod selection;
-pub mod camera;
\ No newline at end of file
+pub mod camera;
+pub mod validate;
\ No newline at end of file
@@ -1,14 +1,22 @@
 use bevy::{prelude::*, math::Vec3Swizzles};
+use bevy_rapier2d::prelude::Sensor;
 
-use crate::util::{EntityQuery, Cursor};
+use crate::{input::Action, util::{EntityQuery, Cursor}};
 
 use super::components::*;
 
+/// Turns a shape about z, rebuilding the rotation from its angle so repeated
+/// turns don't drift into the other axes the way multiplying quaternions does
+fn rotate_around(transform: &mut Transform, center: Vec2, angle: f32) {
+    let offset = Vec2::from_angle(angle).rotate(transform.translation.xy() - center);
+    transform.translation = (center + offset).extend(transform.translation.z);
+    transform.rotation = Quat::from_rotation_z(transform.rotation.to_euler(EulerRot::XYZ).2 + angle);
+}
+
 pub fn selection_manipulation(
     mut commands: Commands,
 
-    keyboard: Res<Input<KeyCode>>,
-    mouse_input: Res<Input<MouseButton>>,
+    actions: Res<Input<Action>>,
     cursor: Res<Cursor>,
 
     selected: EntityQuery<Selected>,
@@ -18,40 +26,44 @@ pub fn selection_manipulation(
     mut transform_query: Query<&mut Transform>,
 ) {
     let single = selected.iter().len() == 1;
-    let multiplier = match keyboard.pressed(KeyCode::LShift) {
+    // Collectibles can be selected and moved, everything else only applies to shapes
+    let selected_shapes: Vec<Entity> = selected.iter().filter(|entity| editor_shape_query.contains(*entity)).collect();
+    let multiplier = match actions.pressed(Action::Modifier) {
         true => 0.2,
         false => 1.0,
     };
 
     if single {
         let entity = selected.single();
+        let resizable = selected_shapes.contains(&entity);
         let mut transform = transform_query.get_mut(entity).unwrap();
 
-        if keyboard.pressed(KeyCode::Up) {
+        if resizable && actions.pressed(Action::Taller) {
             transform.scale.y += 1.0 * multiplier;
         }
 
-        if keyboard.pressed(KeyCode::Down) {
+        if resizable && actions.pressed(Action::Shorter) {
             transform.scale.y -= 1.0 * multiplier;
         }
 
-        if keyboard.pressed(KeyCode::Right) {
+        if resizable && actions.pressed(Action::Wider) {
             transform.scale.x += 1.0 * multiplier;
         }
 
-        if keyboard.pressed(KeyCode::Left) {
+        if resizable && actions.pressed(Action::Narrower) {
             transform.scale.x -= 1.0 * multiplier;
         }
 
-        if keyboard.pressed(KeyCode::E) {
-            transform.rotation *= Quat::from_axis_angle(Vec3::Z, -0.05 * multiplier);
+        let center = transform.translation.xy();
+        if actions.pressed(Action::RotateClockwise) {
+            rotate_around(&mut transform, center, -0.05 * multiplier);
         }
 
-        if keyboard.pressed(KeyCode::Q) {
-            transform.rotation *= Quat::from_axis_angle(Vec3::Z, 0.05 * multiplier);
+        if actions.pressed(Action::RotateCounterClockwise) {
+            rotate_around(&mut transform, center, 0.05 * multiplier);
         }
     } else {
-        if keyboard.any_pressed([KeyCode::Q, KeyCode::E]) {
+        if actions.any_pressed([Action::RotateCounterClockwise, Action::RotateClockwise]) {
             let mut center_pt = Vec2::ZERO;
             let avg_mul = 1.0 / selected.iter().len() as f32;
 
@@ -60,58 +72,108 @@ pub fn selection_manipulation(
                 center_pt += translation.xy() * avg_mul;
             }
 
-            let mut rot = Quat::from_axis_angle(Vec3::Z, 0.0);
-            if keyboard.pressed(KeyCode::E) {
-                rot *= Quat::from_axis_angle(Vec3::Z, -0.05 * multiplier);
+            let mut angle = 0.0;
+            if actions.pressed(Action::RotateClockwise) {
+                angle -= 0.05 * multiplier;
             }
     
-            if keyboard.pressed(KeyCode::Q) {
-                rot *= Quat::from_axis_angle(Vec3::Z, 0.05 * multiplier);
+            if actions.pressed(Action::RotateCounterClockwise) {
+                angle += 0.05 * multiplier;
             }
 
             for entity in selected.iter() {
                 let mut transform = transform_query.get_mut(entity).unwrap();
-                transform.rotate_around(center_pt.extend(0.0), rot);
+                rotate_around(&mut transform, center_pt, angle);
             }
         }
     }
 
-    if keyboard.just_pressed(KeyCode::X) {
-        for entity in selected.iter() {
+    if actions.just_pressed(Action::ToggleStickable) {
+        for entity in selected_shapes.iter().copied() {
             let mut editor_shape = editor_shape_query.get_mut(entity).unwrap();
             editor_shape.stickable = !editor_shape.stickable;
         }
     }
 
-    if keyboard.just_pressed(KeyCode::W) {
+    if actions.just_pressed(Action::CycleReleaseAfter) {
+        for entity in selected_shapes.iter().copied() {
+            let mut editor_shape = editor_shape_query.get_mut(entity).unwrap();
+            editor_shape.release_after = match editor_shape.release_after {
+                None => Some(1.0),
+                Some(t) if t < 3.0 => Some(3.0),
+                Some(_) => None,
+            };
+            info!("Release after: {:?}", editor_shape.release_after);
+        }
+    }
+
+    if actions.just_pressed(Action::ToggleGoal) {
+        for entity in selected_shapes.iter().copied() {
+            let mut editor_shape = editor_shape_query.get_mut(entity).unwrap();
+            editor_shape.goal = !editor_shape.goal;
+        }
+    }
+
+    if actions.just_pressed(Action::ToggleCheckpoint) {
+        let mut next = editor_shape_query.iter().filter_map(|s| s.checkpoint).max().map_or(0, |n| n + 1);
+        for entity in selected_shapes.iter().copied() {
+            let mut editor_shape = editor_shape_query.get_mut(entity).unwrap();
+            editor_shape.checkpoint = match editor_shape.checkpoint {
+                Some(_) => None,
+                None => {
+                    next += 1;
+                    Some(next - 1)
+                },
+            };
+            info!("Checkpoint: {:?}", editor_shape.checkpoint);
+        }
+    }
+
+    if actions.just_pressed(Action::CycleLogic) {
+        for entity in selected_shapes.iter().copied() {
+            let mut editor_shape = editor_shape_query.get_mut(entity).unwrap();
+            editor_shape.logic = ShapeLogic::cycled(editor_shape.logic);
+
+            // Triggers and platforms collide differently, like when spawned
+            let mut entity_commands = commands.entity(entity);
+            entity_commands.insert_bundle((shape_collision_groups(editor_shape.logic), shape_rigid_body(editor_shape.logic)));
+            match editor_shape.logic == Some(ShapeLogic::Trigger) {
+                true => entity_commands.insert(Sensor),
+                false => entity_commands.remove::<Sensor>(),
+            };
+            info!("Logic: {:?}", editor_shape.logic);
+        }
+    }
+
+    if actions.just_pressed(Action::NudgeUp) {
         for entity in selected.iter() {
             let mut transform = transform_query.get_mut(entity).unwrap();
             transform.translation.y += 2.0 * multiplier;
         }
     }
 
-    if keyboard.just_pressed(KeyCode::S) {
+    if actions.just_pressed(Action::NudgeDown) {
         for entity in selected.iter() {
             let mut transform = transform_query.get_mut(entity).unwrap();
             transform.translation.y -= 2.0 * multiplier;
         }
     }
 
-    if keyboard.just_pressed(KeyCode::D) {
+    if actions.just_pressed(Action::NudgeRight) {
         for entity in selected.iter() {
             let mut transform = transform_query.get_mut(entity).unwrap();
             transform.translation.x += 2.0 * multiplier;
         }
     }
 
-    if keyboard.just_pressed(KeyCode::A) {
+    if actions.just_pressed(Action::NudgeLeft) {
         for entity in selected.iter() {
             let mut transform = transform_query.get_mut(entity).unwrap();
             transform.translation.x -= 2.0 * multiplier;
         }
     }
 
-    if !hovered.is_empty() && mouse_input.just_pressed(MouseButton::Left) {
+    if !hovered.is_empty() && actions.just_pressed(Action::Select) {
         let mut contains = false;
         for entity in hovered.iter() {
             if selected.contains(entity) {
@@ -131,7 +193,7 @@ pub fn selection_manipulation(
         }
     }
 
-    if mouse_input.pressed(MouseButton::Left) && !mouse_input.just_pressed(MouseButton::Left) {
+    if actions.pressed(Action::Select) && !actions.just_pressed(Action::Select) {
         for entity in selected.iter() {
             let mut transform = transform_query.get_mut(entity).unwrap();
             transform.translation -= cursor.delta.extend(0.0);
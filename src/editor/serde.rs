@@ -1,54 +1,186 @@
-use std::fs;
+use std::{f32::consts::PI, fs, io, path::Path};
 
 use serde::{Serialize, Deserialize};
-use bevy::{prelude::*, math::Vec3Swizzles, utils::Instant};
+use bevy::{prelude::*, math::Vec3Swizzles, utils::{Instant, HashMap}};
 
-use crate::util::EntityQuery;
+use crate::{input::Action, game::{level::{LevelHash, CurrentLevel, LevelCamera, LevelRect}, collectible::{Collectible, spawn_collectible}, logic::{Wire, LevelWires}}};
 
-use super::{components::*, editor::SpawnShapeParam};
+use super::{components::*, editor::SpawnShapeParam, validate::validate_level, format::{detect_format, format_for_path, JsonFormat}};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// Both shape types look the same turned halfway around, so angles are kept in [-PI/2, PI/2)
+pub fn canonical_angle(angle: f32) -> f32 {
+    (angle + PI / 2.0).rem_euclid(PI) - PI / 2.0
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SerdeShape {
-    shape: EditorShape,
-    translation: Vec2,
-    rotation: Quat,
-    scale: Vec2,
+    // Files from before shapes had ids get them in file order when loaded
+    #[serde(default, skip_serializing_if = "ShapeId::is_unset")]
+    pub id: ShapeId,
+    pub shape: EditorShape,
+    pub translation: Vec2,
+    // Radians counterclockwise
+    pub angle: f32,
+    // Half the width and height, never negative
+    pub scale: Vec2,
 }
 
 impl SerdeShape {
+    /// Takes the 2D part of an ECS transform, normalized
     pub fn new(shape: EditorShape, transform: &Transform) -> Self {
         Self {
+            id: ShapeId::default(),
             shape,
             translation: transform.translation.xy(),
-            rotation: transform.rotation,
+            angle: transform.rotation.to_euler(EulerRot::XYZ).2,
             scale: transform.scale.xy(),
+        }.normalized()
+    }
+
+    /// Flipping a shape along either axis doesn't change it, so negative scales are made positive
+    pub fn normalized(self) -> Self {
+        Self {
+            angle: canonical_angle(self.angle),
+            scale: self.scale.abs(),
+            ..self
         }
     }
 
-    fn spawn(self, commands: &mut Commands, param: &mut SpawnShapeParam) {
-        self.shape.spawn(
-            commands,
-            param, 
-            &Transform::from_translation(self.translation.extend(0.0))
-                .with_rotation(self.rotation)
-                .with_scale(self.scale.extend(1.0))
-        );
+    pub fn transform(&self, z: f32) -> Transform {
+        Transform::from_translation(self.translation.extend(z))
+            .with_rotation(Quat::from_rotation_z(self.angle))
+            .with_scale(self.scale.extend(1.0))
     }
+
+    /// Box around the shape however it's rotated. Ovals get the box of their rectangle.
+    pub fn aabb(&self) -> LevelRect {
+        let axis_x = Vec2::from_angle(self.angle);
+        let half_size = axis_x.abs() * self.scale.x.abs() + axis_x.perp().abs() * self.scale.y.abs();
+        LevelRect { min: self.translation - half_size, max: self.translation + half_size }
+    }
+
+    pub fn spawn(self, commands: &mut Commands, param: &mut SpawnShapeParam) -> Entity {
+        let transform = self.transform(0.0);
+        self.shape.spawn(commands, param, self.id, &transform)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SerdeCollectible {
+    pub id: u32,
+    pub translation: Vec2,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+impl SerdeCollectible {
+    pub fn spawn(self, commands: &mut Commands, param: &mut SpawnShapeParam) -> Entity {
+        spawn_collectible(commands, param, Collectible { id: self.id }, self.translation)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SerdeLevel {
-    shapes: Vec<SerdeShape>,
+    pub shapes: Vec<SerdeShape>,
+    // Left out when empty so levels without it keep their hash
+    #[serde(default, skip_serializing_if = "LevelCamera::is_empty")]
+    pub camera: LevelCamera,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub collectibles: Vec<SerdeCollectible>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wires: Vec<Wire>,
 }
 
 impl SerdeLevel {
     pub fn new() -> Self {
-        Self { shapes: Vec::new() }
+        Self { shapes: Vec::new(), camera: LevelCamera::default(), collectibles: Vec::new(), wires: Vec::new() }
+    }
+
+    pub fn collectible_count(&self) -> usize {
+        self.collectibles.len()
+    }
+
+    pub fn next_shape_id(&self) -> ShapeId {
+        ShapeId(self.shapes.iter().map(|shape| shape.id.0).max().unwrap_or(0) + 1)
+    }
+
+    /// Gives every shape without an id the next free one, in file order
+    pub fn assign_shape_ids(&mut self) {
+        let mut next = self.next_shape_id();
+        for shape in self.shapes.iter_mut().filter(|shape| shape.id.is_unset()) {
+            shape.id = next;
+            next.0 += 1;
+        }
+    }
+
+    /// Orders shapes and collectibles by id and wires by what they connect, so saving
+    /// the same level always writes the same file
+    pub fn sort(&mut self) {
+        self.shapes.sort_by_key(|shape| shape.id);
+        self.collectibles.sort_by_key(|collectible| collectible.id);
+        self.wires.sort();
+        self.wires.dedup();
+    }
+
+    /// Adds everything in `other` to this level. Its shapes and collectibles are renumbered
+    /// after this level's, and the bounds grow to cover both if both have them.
+    pub fn merge(&mut self, other: SerdeLevel) {
+        let first_id = self.collectibles.iter().map(|c| c.id + 1).max().unwrap_or(0);
+        self.collectibles.extend(other.collectibles.into_iter()
+            .map(|c| SerdeCollectible { id: c.id + first_id, ..c }));
+
+        let first_shape_id = self.next_shape_id().0;
+        self.shapes.extend(other.shapes.into_iter()
+            .map(|shape| SerdeShape { id: ShapeId(shape.id.0 + first_shape_id), ..shape }));
+        self.wires.extend(other.wires.into_iter().map(|wire| Wire {
+            from: ShapeId(wire.from.0 + first_shape_id),
+            to: ShapeId(wire.to.0 + first_shape_id),
+            ..wire
+        }));
+        self.camera.zones.extend(other.camera.zones);
+        self.camera.bounds = match (self.camera.bounds, other.camera.bounds) {
+            (Some(a), Some(b)) => Some(LevelRect { min: a.min.min(b.min), max: a.max.max(b.max) }),
+            // One side could be anywhere
+            _ => None,
+        };
+    }
+
+    /// Loads a level in any format, whatever its extension says
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let mut level = detect_format(&bytes).decode(&bytes)?;
+        level.assign_shape_ids();
+        Ok(level)
+    }
+
+    /// Writes the level in the format matching the extension of `path`, JSON if it has no known one
+    pub fn save(&self, path: &Path, pretty: bool) -> io::Result<()> {
+        let format = format_for_path(path, pretty).unwrap_or_else(|| Box::new(JsonFormat { pretty }));
+        fs::write(path, format.encode(self)?)
     }
 
     pub fn push(&mut self, shape: SerdeShape) {
         self.shapes.push(shape);
     }
+
+    /// Shape ids are left out, they only tell shapes apart and giving them out doesn't change the level.
+    /// Wires refer to shapes by position instead.
+    pub fn content_hash(&self) -> u64 {
+        let mut level = self.clone();
+        let positions: HashMap<ShapeId, u32> = self.shapes.iter().zip(0..).map(|(shape, i)| (shape.id, i)).collect();
+        for wire in level.wires.iter_mut() {
+            wire.from = ShapeId(positions.get(&wire.from).copied().unwrap_or(u32::MAX));
+            wire.to = ShapeId(positions.get(&wire.to).copied().unwrap_or(u32::MAX));
+        }
+        for shape in level.shapes.iter_mut() {
+            shape.id = ShapeId::default();
+        }
+        content_hash(&level)
+    }
+}
+
+/// FNV-1a over the serialized level, so formatting changes to the file don't count
+pub fn content_hash(level: &impl Serialize) -> u64 {
+    serde_json::to_string(level).unwrap().bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
 }
 
 pub struct SaveLoaded(pub bool);
@@ -57,46 +189,72 @@ pub fn editor_load(
     mut commands: Commands,
 
     mut loaded: ResMut<SaveLoaded>,
+    mut level_hash: ResMut<LevelHash>,
+    mut level_camera: ResMut<LevelCamera>,
+    mut level_wires: ResMut<LevelWires>,
+    current_level: Res<CurrentLevel>,
 
     mut spawn_shape_param: SpawnShapeParam,
 ) {
     if loaded.0 { return }
     if spawn_shape_param.level.get_single().is_err() { return }
 
-    let contents = serde_json::from_str::<SerdeLevel>(
-        fs::read_to_string("./level.json").unwrap().as_str()
-    ).unwrap();
+    let contents = match SerdeLevel::load(&current_level) {
+        Ok(contents) => contents,
+        // Saving creates it, like naming a new level to edit
+        Err(e) if e.kind() == io::ErrorKind::NotFound => SerdeLevel::new(),
+        Err(e) => {
+            error!("Couldn't load {}: {}", current_level.display(), e);
+            SerdeLevel::new()
+        },
+    };
+    **level_hash = contents.content_hash();
+    **spawn_shape_param.next_shape_id = contents.next_shape_id();
+    *level_camera = contents.camera;
+    *level_wires = LevelWires(contents.wires);
 
     for shape in contents.shapes {
         shape.spawn(&mut commands, &mut spawn_shape_param);
     }
+    for collectible in contents.collectibles {
+        collectible.spawn(&mut commands, &mut spawn_shape_param);
+    }
     loaded.0 = true;
 }
 
 pub fn editor_save(
-    keyboard_input: Res<Input<KeyCode>>,
+    actions: Res<Input<Action>>,
+    mut level_hash: ResMut<LevelHash>,
+    level_camera: Res<LevelCamera>,
+    level_wires: Res<LevelWires>,
+    current_level: Res<CurrentLevel>,
 
-    shapes: EntityQuery<EditorShape>,
-
-    transform_query: Query<&Transform>,
-    editor_shape_query: Query<&EditorShape>,
+    shapes: Query<(&ShapeId, &EditorShape, &Transform)>,
+    collectibles: Query<(&Collectible, &Transform)>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::P) {
+    if actions.just_pressed(Action::SaveLevel) {
         let start = Instant::now();
         let mut serde_level = SerdeLevel::new();
+        serde_level.camera = level_camera.clone();
+        serde_level.wires = level_wires.0.clone();
 
-        for shape in shapes.iter() {
-            let transform = transform_query.get(shape).unwrap();
-            let editor_shape = editor_shape_query.get(shape).unwrap();
-
-            let serde_shape = SerdeShape::new(editor_shape.clone(), transform);
+        for (id, editor_shape, transform) in shapes.iter() {
+            let serde_shape = SerdeShape { id: *id, ..SerdeShape::new(editor_shape.clone(), transform) };
             serde_level.push(serde_shape);
         }
 
-        fs::write(
-            "./level.json",
-            serde_json::to_string_pretty(&serde_level).unwrap().as_str()
-        ).expect("death");
+        serde_level.collectibles = collectibles.iter()
+            .map(|(collectible, transform)| SerdeCollectible { id: collectible.id, translation: transform.translation.xy() })
+            .collect();
+        // Sorted so saving doesn't change the file or the hash when nothing moved
+        serde_level.sort();
+        **level_hash = serde_level.content_hash();
+
+        for issue in validate_level(&serde_level) {
+            warn!("{}", issue);
+        }
+
+        serde_level.save(&current_level, true).expect("death");
 
         info!("Saved in {}ms", Instant::now().duration_since(start).as_millis());
     }
@@ -0,0 +1,238 @@
+use std::{fmt, path::Path};
+
+use bevy::prelude::*;
+
+use crate::game::player::{controls::controls::MAX_WEB_LENGTH, spawn::SPAWN_POINT};
+
+use super::{components::{ShapeType, ShapeLogic}, serde::{SerdeLevel, SerdeShape, canonical_angle}};
+
+// Shapes thinner than this can't be landed on or hit reliably
+pub const MIN_SCALE: f32 = 1.0;
+// Shapes this close in position and size are treated as the same shape placed twice
+pub const DUPLICATE_TOLERANCE: f32 = 2.0;
+// Without level bounds, anything further from the spawn than this is lost
+pub const MAX_COORDINATE: f32 = 100_000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    // The level can't be played properly until it's fixed
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelIssue {
+    pub severity: Severity,
+    // Index into the level's shapes, when the issue is about one
+    pub shape: Option<usize>,
+    pub message: String,
+}
+
+impl LevelIssue {
+    fn error(shape: Option<usize>, message: String) -> Self {
+        Self { severity: Severity::Error, shape, message }
+    }
+
+    fn warning(shape: Option<usize>, message: String) -> Self {
+        Self { severity: Severity::Warning, shape, message }
+    }
+}
+
+impl fmt::Display for LevelIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match self.shape {
+            Some(shape) => write!(f, "{}: shape {}: {}", severity, shape, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+fn is_bad_float(value: f32) -> bool {
+    !value.is_finite() || value.is_subnormal()
+}
+
+/// Distance from `point` to the edge of the shape, 0 inside it. Ovals are
+/// measured along the line to their center, which is exact for circles.
+pub fn distance_to_shape(shape: &SerdeShape, point: Vec2) -> f32 {
+    let local = Vec2::from_angle(-shape.angle).rotate(point - shape.translation);
+    let half_size = shape.scale.abs();
+
+    match shape.shape.shape_type {
+        ShapeType::Rectangle => local.distance(local.clamp(-half_size, half_size)),
+        ShapeType::Oval => {
+            let unit = (local / half_size).length();
+            match unit <= 1.0 {
+                true => 0.0,
+                false => local.length() * (1.0 - 1.0 / unit),
+            }
+        },
+    }
+}
+
+fn is_duplicate(a: &SerdeShape, b: &SerdeShape) -> bool {
+    let same_type = matches!(
+        (&a.shape.shape_type, &b.shape.shape_type),
+        (ShapeType::Rectangle, ShapeType::Rectangle) | (ShapeType::Oval, ShapeType::Oval)
+    );
+
+    same_type
+        && a.translation.distance(b.translation) <= DUPLICATE_TOLERANCE
+        && (a.scale - b.scale).abs().max_element() <= DUPLICATE_TOLERANCE
+        && canonical_angle(a.angle - b.angle).abs() <= 0.01
+}
+
+/// Everything wrong with a level that would make it broken or unfair to play
+pub fn validate_level(level: &SerdeLevel) -> Vec<LevelIssue> {
+    let mut issues = Vec::new();
+
+    for (i, shape) in level.shapes.iter().enumerate() {
+        let bad_floats = shape.translation.to_array().into_iter()
+            .chain([shape.angle])
+            .chain(shape.scale.to_array())
+            .any(is_bad_float);
+        if bad_floats {
+            issues.push(LevelIssue::error(Some(i), "transform has NaN, infinite or denormal values".to_string()));
+            // Nothing else can be said about it
+            continue
+        }
+
+        if shape.clone().normalized() != *shape {
+            issues.push(LevelIssue::warning(Some(i), format!("transform isn't normalized (angle {}, scale {})", shape.angle, shape.scale)));
+        }
+
+        if shape.scale.abs().min_element() < MIN_SCALE {
+            issues.push(LevelIssue::error(Some(i), format!("degenerate scale {}x{}", shape.scale.x, shape.scale.y)));
+        }
+
+        let outside = match &level.camera.bounds {
+            Some(bounds) => !bounds.contains(shape.translation),
+            None => shape.translation.abs().max_element() > MAX_COORDINATE,
+        };
+        if outside {
+            issues.push(LevelIssue::warning(Some(i), format!("outside the level bounds at {}", shape.translation)));
+        }
+
+        if let Some(ShapeLogic::Platform { offset, period }) = shape.shape.logic {
+            if is_bad_float(offset.x) || is_bad_float(offset.y) || is_bad_float(period) || period <= 0.0 {
+                issues.push(LevelIssue::error(Some(i), format!("platform can't move {} every {} seconds", offset, period)));
+            }
+        }
+
+        for (j, other) in level.shapes.iter().enumerate().skip(i + 1) {
+            if is_duplicate(shape, other) {
+                issues.push(LevelIssue::warning(Some(j), format!("duplicate of shape {}", i)));
+            }
+        }
+    }
+
+    // Triggers can be walked through
+    let covers_spawn = |shape: &SerdeShape| shape.shape.logic != Some(ShapeLogic::Trigger) && distance_to_shape(shape, SPAWN_POINT) == 0.0;
+    if let Some(i) = level.shapes.iter().position(covers_spawn) {
+        issues.push(LevelIssue::error(Some(i), format!("covers the spawn point at {}", SPAWN_POINT)));
+    }
+
+    if !level.shapes.iter().any(|shape| shape.shape.goal) {
+        issues.push(LevelIssue::error(None, "no goal, runs can never finish".to_string()));
+    }
+
+    for wire in level.wires.iter() {
+        let position = |id| level.shapes.iter().position(|shape| shape.id == id);
+        let (from, to) = match (position(wire.from), position(wire.to)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => {
+                issues.push(LevelIssue::error(None, format!("wire {} -> {} connects a shape that doesn't exist", wire.from.0, wire.to.0)));
+                continue
+            },
+        };
+
+        if !level.shapes[from].shape.logic.is_some_and(|logic| logic.is_source()) {
+            issues.push(LevelIssue::warning(Some(from), format!("wired to shape {} but isn't a trigger or switch", to)));
+        }
+        if !level.shapes[to].shape.logic.is_some_and(|logic| !logic.is_source()) {
+            issues.push(LevelIssue::warning(Some(to), format!("wired from shape {} but isn't a door, platform or hazard", from)));
+        }
+    }
+
+    let nearest_stickable = level.shapes.iter()
+        .filter(|shape| shape.shape.stickable)
+        .map(|shape| distance_to_shape(shape, SPAWN_POINT))
+        .fold(f32::INFINITY, f32::min);
+    if nearest_stickable > MAX_WEB_LENGTH {
+        issues.push(LevelIssue::error(None, format!("no stickable surface within {} of the spawn", MAX_WEB_LENGTH)));
+    }
+
+    issues
+}
+
+/// Prints every issue in the levels at `paths`. Returns false if any level has errors or doesn't load.
+pub fn report_levels(paths: &[&Path]) -> bool {
+    let mut ok = true;
+
+    for path in paths {
+        let level = match SerdeLevel::load(path) {
+            Ok(level) => level,
+            Err(e) => {
+                println!("{}: error: couldn't load: {}", path.display(), e);
+                ok = false;
+                continue
+            },
+        };
+
+        let issues = validate_level(&level);
+        for issue in issues.iter() {
+            println!("{}: {}", path.display(), issue);
+        }
+        ok &= !issues.iter().any(|issue| issue.severity == Severity::Error);
+
+        if issues.is_empty() {
+            println!("{}: ok", path.display());
+        }
+    }
+
+    ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::editor::components::EditorShape;
+
+    fn rect(x: f32, y: f32, w: f32, h: f32) -> SerdeShape {
+        SerdeShape::new(EditorShape::new(ShapeType::Rectangle, true), &Transform::from_xyz(x, y, 0.0).with_scale(Vec3::new(w, h, 1.0)))
+    }
+
+    #[test]
+    fn flags_broken_shapes_and_missing_goal() {
+        let mut level = SerdeLevel::new();
+        level.push(rect(0.0, -200.0, 300.0, 20.0));
+        level.push(rect(0.5, -200.5, 301.0, 20.0));
+        level.push(rect(500.0, 0.0, 0.2, 50.0));
+        level.push(rect(f32::NAN, 0.0, 10.0, 10.0));
+
+        let issues = validate_level(&level);
+        let shape_issues = |i| issues.iter().filter(|issue| issue.shape == Some(i)).count();
+
+        assert_eq!(shape_issues(0), 0);
+        assert_eq!(shape_issues(1), 1, "{:?}", issues);
+        assert_eq!(shape_issues(2), 1, "{:?}", issues);
+        assert_eq!(shape_issues(3), 1, "{:?}", issues);
+        assert!(issues.iter().any(|issue| issue.shape.is_none() && issue.message.contains("goal")));
+    }
+
+    #[test]
+    fn stickable_surface_must_be_in_web_range() {
+        let mut level = SerdeLevel::new();
+        let mut goal = rect(5000.0, 0.0, 50.0, 50.0);
+        goal.shape.goal = true;
+        level.push(goal);
+        assert!(validate_level(&level).iter().any(|issue| issue.message.contains("stickable")));
+
+        level.push(rect(0.0, 900.0, 300.0, 20.0));
+        assert!(validate_level(&level).is_empty(), "{:?}", validate_level(&level));
+    }
+}
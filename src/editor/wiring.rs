@@ -0,0 +1,85 @@
+use bevy::{prelude::*, math::Vec3Swizzles, sprite::MaterialMesh2dBundle};
+
+use crate::{input::Action, util::{EntityQuery, PreloadedAssets}, game::{level::ShapeIndex, logic::{Wire, LevelWires}}};
+
+use super::components::*;
+
+/// Line drawn in the editor for one wire
+#[derive(Component)]
+pub struct WireVisual;
+
+/// Cycles the wire from every selected shape to the hovered one through wired, inverted and unwired
+pub fn cycle_wires(
+    actions: Res<Input<Action>>,
+    mut wires: ResMut<LevelWires>,
+
+    selected_q: Query<&ShapeId, With<Selected>>,
+    hovered_q: Query<&ShapeId, With<Hovered>>,
+) {
+    if !actions.just_pressed(Action::CycleWire) { return }
+
+    let to = match hovered_q.get_single() {
+        Ok(id) => *id,
+        Err(_) => return,
+    };
+
+    for from in selected_q.iter().copied().filter(|from| *from != to) {
+        let state = match wires.iter().position(|wire| wire.from == from && wire.to == to) {
+            None => {
+                wires.push(Wire { from, to, invert: false });
+                "wired"
+            },
+            Some(i) if !wires[i].invert => {
+                wires[i].invert = true;
+                "inverted"
+            },
+            Some(i) => {
+                wires.remove(i);
+                "removed"
+            },
+        };
+        info!("Wire {} -> {}: {}", from.0, to.0, state);
+    }
+}
+
+/// Redraws every wire as a line between the centers of the shapes it connects,
+/// darker when inverted
+pub fn draw_wires(
+    mut commands: Commands,
+    preload: Res<PreloadedAssets>,
+    wires: Res<LevelWires>,
+    index: Res<ShapeIndex>,
+
+    visuals: EntityQuery<WireVisual>,
+    moved_q: Query<(), (With<ShapeId>, Changed<Transform>)>,
+    transform_q: Query<&Transform>,
+) {
+    if !wires.is_changed() && !index.is_changed() && moved_q.is_empty() { return }
+
+    for entity in visuals.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    for wire in wires.iter() {
+        let ends = [wire.from, wire.to].map(|id| index.get(id).and_then(|entity| transform_q.get(entity).ok()));
+        let (from, to) = match ends {
+            [Some(from), Some(to)] => (from.translation.xy(), to.translation.xy()),
+            _ => continue,
+        };
+
+        let color = match wire.invert {
+            true => Color::rgb(0.35, 0.35, 0.35),
+            false => Color::rgb(0.9, 0.9, 0.9),
+        };
+        let delta = to - from;
+
+        commands.spawn_bundle(MaterialMesh2dBundle {
+            mesh: preload.meshes.get("box 1").unwrap().clone(),
+            material: preload.get_bw_color_handle(color).clone(),
+            transform: Transform::from_translation(((from + to) * 0.5).extend(2.0))
+                .with_rotation(Quat::from_rotation_z(delta.y.atan2(delta.x)))
+                .with_scale(Vec3::new(delta.length(), 3.0, 1.0)),
+            ..default()
+        }).insert(WireVisual);
+    }
+}
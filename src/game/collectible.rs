@@ -0,0 +1,216 @@
+use bevy::{prelude::*, math::Vec3Swizzles, sprite::MaterialMesh2dBundle};
+use bevy_rapier2d::prelude::*;
+
+use crate::{editor::{editor::SpawnShapeParam, components::Selectable}, save::SaveProfile};
+
+use super::{level::LevelHash, player::components::*};
+
+pub const COLLECTIBLE_RADIUS: f32 = 20.0;
+pub const COLLECTIBLE_COLOR: Color = Color::rgb(1.0, 0.8, 0.2);
+// Picked up already, now or in an earlier visit
+pub const FOUND_COLOR: Color = Color::rgba(1.0, 0.8, 0.2, 0.25);
+// Hovered or selected in the editor
+pub const HIGHLIGHTED_COLOR: Color = Color::rgb(1.0, 0.95, 0.7);
+
+/// Optional pickup placed in a level. Ids are unique within the level.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Collectible {
+    pub id: u32,
+}
+
+/// Marks a collectible that's been picked up, so it can't be picked up again
+#[derive(Component)]
+pub struct Found;
+
+/// Sent when a collectible is picked up for the first time
+pub struct CollectibleFound {
+    pub id: u32,
+}
+
+pub struct CollectibleMaterials {
+    pub fresh: Handle<ColorMaterial>,
+    pub found: Handle<ColorMaterial>,
+    pub highlighted: Handle<ColorMaterial>,
+}
+
+impl FromWorld for CollectibleMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.resource_mut::<Assets<ColorMaterial>>();
+        Self {
+            fresh: materials.add(ColorMaterial::from(COLLECTIBLE_COLOR)),
+            found: materials.add(ColorMaterial::from(FOUND_COLOR)),
+            highlighted: materials.add(ColorMaterial::from(HIGHLIGHTED_COLOR)),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct CollectibleText;
+
+pub fn spawn_collectible(commands: &mut Commands, param: &mut SpawnShapeParam, collectible: Collectible, translation: Vec2) -> Entity {
+    let child = commands.spawn_bundle(MaterialMesh2dBundle {
+        mesh: param.preload.meshes.get("circle 1").unwrap().clone(),
+        material: param.collectible_materials.fresh.clone(),
+        transform: Transform::from_translation(translation.extend(1.0))
+            .with_scale(Vec3::new(COLLECTIBLE_RADIUS, COLLECTIBLE_RADIUS, 1.0)),
+        ..default()
+    }).insert_bundle((
+        Collider::ball(1.0),
+        Sensor,
+        // Only overlaps the player's body and web, and stays out of every raycast
+        CollisionGroups::new(
+            Group::from_bits_truncate(0b1000),
+            Group::from_bits_truncate(0b10),
+        ),
+        collectible,
+        Selectable,
+    )).id();
+
+    commands.entity(param.level.single()).add_child(child);
+    child
+}
+
+/// Closest distance from `point` to the segment between `a` and `b`
+fn distance_to_segment(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = match ab.length_squared() > 0.0 {
+        true => ((point - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0),
+        false => 0.0,
+    };
+    point.distance(a + ab * t)
+}
+
+/// Picks up collectibles touching the body or the web. The chain web is made of
+/// colliders, the wrapping rope only of points, so it's checked by distance.
+pub fn collect_items(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    mut found_events: EventWriter<CollectibleFound>,
+
+    player_q: Query<(&Player, Option<&WrappingRope>)>,
+    web_part_q: Query<(), With<WebPart>>,
+    transform_q: Query<&Transform>,
+    collectible_q: Query<(Entity, &Collectible), Without<Found>>,
+) {
+    let (player, rope) = match player_q.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    let rope_points = rope.map(|rope| {
+        let arm_l_transform = transform_q.get(player.arm_l).unwrap();
+        let hand_l_position = (arm_l_transform.rotation.mul_vec3(Vec3::X * 15.0) + arm_l_transform.translation).xy();
+        rope.points(hand_l_position)
+    });
+
+    for (entity, collectible) in collectible_q.iter() {
+        let touching_collider = rapier_context.intersections_with(entity)
+            .filter(|(_, _, intersecting)| *intersecting)
+            .map(|(e1, e2, _)| if e1 == entity { e2 } else { e1 })
+            .any(|other| other == player.body || web_part_q.contains(other));
+
+        let center = transform_q.get(entity).unwrap().translation.xy();
+        let touching_rope = rope_points.as_ref().is_some_and(|points| {
+            points.windows(2).any(|segment| distance_to_segment(center, segment[0], segment[1]) <= COLLECTIBLE_RADIUS)
+        });
+
+        if touching_collider || touching_rope {
+            commands.entity(entity).insert(Found);
+            found_events.send(CollectibleFound { id: collectible.id });
+        }
+    }
+}
+
+pub fn save_found_collectibles(
+    level_hash: Res<LevelHash>,
+    mut profile: ResMut<SaveProfile>,
+    mut found_events: EventReader<CollectibleFound>,
+) {
+    for event in found_events.iter() {
+        if profile.data.find_collectible(**level_hash, event.id) {
+            info!("Found collectible {}", event.id);
+            profile.mark_dirty();
+        }
+    }
+}
+
+/// Collectibles already in the save start out found when the level loads
+pub fn mark_saved_collectibles(
+    mut commands: Commands,
+    level_hash: Res<LevelHash>,
+    profile: Res<SaveProfile>,
+    collectible_q: Query<(Entity, &Collectible), Added<Collectible>>,
+) {
+    let found = match profile.data.found_collectibles(**level_hash) {
+        Some(found) => found,
+        None => return,
+    };
+
+    for (entity, collectible) in collectible_q.iter() {
+        if found.contains(&collectible.id) {
+            commands.entity(entity).insert(Found);
+        }
+    }
+}
+
+pub fn fade_found_collectibles(
+    materials: Res<CollectibleMaterials>,
+    mut collectible_q: Query<&mut Handle<ColorMaterial>, (With<Collectible>, Added<Found>)>,
+) {
+    for mut material in collectible_q.iter_mut() {
+        *material = materials.found.clone();
+    }
+}
+
+pub fn spawn_collectible_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.spawn_bundle(TextBundle::from_section(
+        "",
+        TextStyle {
+            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+            font_size: 32.0,
+            color: COLLECTIBLE_COLOR,
+        },
+    ).with_style(Style {
+        position_type: PositionType::Absolute,
+        position: UiRect {
+            top: Val::Px(20.0),
+            right: Val::Px(20.0),
+            ..default()
+        },
+        ..default()
+    })).insert(CollectibleText);
+}
+
+/// Found out of total for the loaded level, hidden when it has none
+pub fn update_collectible_hud(
+    collectible_q: Query<Option<&Found>, With<Collectible>>,
+    mut text_q: Query<&mut Text, With<CollectibleText>>,
+) {
+    let mut text = match text_q.get_single_mut() {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+
+    let total = collectible_q.iter().count();
+    let found = collectible_q.iter().filter(|found| found.is_some()).count();
+    text.sections[0].value = match total {
+        0 => String::new(),
+        _ => format!("{}/{}", found, total),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_to_segment_clamps_to_the_ends() {
+        let (a, b) = (Vec2::ZERO, Vec2::new(10.0, 0.0));
+        assert_eq!(distance_to_segment(Vec2::new(5.0, 3.0), a, b), 3.0);
+        assert_eq!(distance_to_segment(Vec2::new(14.0, 3.0), a, b), 5.0);
+        assert_eq!(distance_to_segment(Vec2::new(1.0, 1.0), a, a), 2.0_f32.sqrt());
+    }
+}
@@ -0,0 +1,158 @@
+use bevy::{prelude::*, math::Vec3Swizzles};
+use bevy_rapier2d::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::util::MainCamera;
+
+use super::player::{camera::FollowCamera, components::*, respawn_message::MESSAGE_VELOCITY};
+
+/// Shake and hit-stop tuning. Both can be turned off for players sensitive to them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct FeedbackSettings {
+    pub screen_shake: bool,
+    pub hit_stop: bool,
+    // Impacts slower than this into a surface do nothing, full trauma at `MESSAGE_VELOCITY`
+    pub shake_speed: f32,
+    // Trauma lost per second
+    pub trauma_decay: f32,
+    pub max_offset: f32,
+    // Radians
+    pub max_angle: f32,
+    // Impacts at least this fast freeze the game for `impact_stop_frames`
+    pub hard_impact_speed: f32,
+    pub impact_stop_frames: u32,
+    pub attach_stop_frames: u32,
+}
+
+impl Default for FeedbackSettings {
+    fn default() -> Self {
+        Self {
+            screen_shake: true,
+            hit_stop: true,
+            shake_speed: 1500.0,
+            trauma_decay: 1.5,
+            max_offset: 40.0,
+            max_angle: 0.03,
+            hard_impact_speed: 3000.0,
+            impact_stop_frames: 4,
+            attach_stop_frames: 2,
+        }
+    }
+}
+
+/// Shake amount from 0 to 1. The shake itself grows with its square, so small bumps stay subtle.
+#[derive(Default)]
+pub struct CameraShake {
+    pub trauma: f32,
+}
+
+impl CameraShake {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+}
+
+/// Freezes the game for a few frames. Frozen frames count as paused, so they
+/// don't add to run times or recordings.
+#[derive(Default)]
+pub struct HitStop {
+    pending: u32,
+    frozen: bool,
+}
+
+impl HitStop {
+    pub fn start(&mut self, frames: u32) {
+        self.pending = self.pending.max(frames);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+}
+
+/// Body velocity from the frame before, since contacts have already bounced it by the time they're reported
+#[derive(Default)]
+pub struct LastBodyVelocity(pub Vec2);
+
+pub fn tick_hit_stop(mut hit_stop: ResMut<HitStop>) {
+    hit_stop.frozen = hit_stop.pending > 0;
+    hit_stop.pending = hit_stop.pending.saturating_sub(1);
+}
+
+pub fn impact_feedback(
+    settings: Res<FeedbackSettings>,
+    mut shake: ResMut<CameraShake>,
+    mut hit_stop: ResMut<HitStop>,
+    mut last_velocity: ResMut<LastBodyVelocity>,
+    mut contact_forces: EventReader<ContactForceEvent>,
+
+    player_q: Query<&Player>,
+    velocity_q: Query<&Velocity>,
+) {
+    let body = match player_q.get_single() {
+        Ok(player) => player.body,
+        Err(_) => return,
+    };
+
+    // Only the speed going into the surface counts, so sliding along a wall doesn't shake
+    let impact_speed = contact_forces.iter()
+        .filter(|event| event.collider1 == body || event.collider2 == body)
+        .map(|event| last_velocity.0.dot(event.max_force_direction).abs())
+        .fold(0.0, f32::max);
+
+    if settings.screen_shake && impact_speed > settings.shake_speed {
+        shake.add_trauma((impact_speed - settings.shake_speed) / (MESSAGE_VELOCITY - settings.shake_speed));
+    }
+
+    if settings.hit_stop && impact_speed >= settings.hard_impact_speed {
+        hit_stop.start(settings.impact_stop_frames);
+    }
+
+    if let Ok(velocity) = velocity_q.get(body) {
+        last_velocity.0 = velocity.linvel;
+    }
+}
+
+pub fn attach_feedback(
+    settings: Res<FeedbackSettings>,
+    mut hit_stop: ResMut<HitStop>,
+    mut state_events: EventReader<PlayerStateChanged>,
+) {
+    let attached = state_events.iter().any(|event| event.to == PlayerState::Attached);
+    if settings.hit_stop && attached {
+        hit_stop.start(settings.attach_stop_frames);
+    }
+}
+
+// Smooth values between -1 and 1, different for each seed
+fn shake_noise(t: f32, seed: f32) -> f32 {
+    ((t * 23.0 + seed).sin() + (t * 37.0 + seed * 1.7).sin()) / 2.0
+}
+
+/// Offsets the main camera on top of where the follow camera put it
+pub fn camera_shake(
+    time: Res<Time>,
+    settings: Res<FeedbackSettings>,
+    mut shake: ResMut<CameraShake>,
+    mut camera_q: Query<(&mut Transform, &mut FollowCamera), With<MainCamera>>,
+) {
+    shake.trauma = (shake.trauma - settings.trauma_decay * time.delta_seconds()).max(0.0);
+    if !settings.screen_shake {
+        shake.trauma = 0.0;
+    }
+
+    let (mut transform, mut follow) = match camera_q.get_single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+
+    let t = time.seconds_since_startup() as f32;
+    let amount = shake.trauma * shake.trauma;
+    let offset = Vec2::new(shake_noise(t, 0.0), shake_noise(t, 10.0)) * settings.max_offset * amount;
+
+    let unshaken = transform.translation.xy() - follow.shake_offset;
+    transform.translation = (unshaken + offset).extend(transform.translation.z);
+    transform.rotation = Quat::from_rotation_z(shake_noise(t, 20.0) * settings.max_angle * amount);
+    follow.shake_offset = offset;
+}
@@ -0,0 +1,286 @@
+use std::{fs, io, path::PathBuf};
+
+use bevy::{prelude::*, math::Vec3Swizzles};
+
+use crate::util::{ByteReader, ExtraTransformMethods, push_vec2, invalid_data};
+
+use super::{level::LevelHash, run::{CurrentRun, RunFinished}};
+
+pub const GHOST_DIR: &str = "./ghosts";
+pub const GHOST_MAGIC: &[u8; 4] = b"SPGH";
+pub const GHOST_VERSION: u8 = 1;
+
+pub const GHOST_ALPHA: f32 = 0.3;
+pub const GHOST_Z: f32 = -20.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pose {
+    pub pos: Vec2,
+    pub angle: f32,
+}
+
+impl Pose {
+    pub fn from_transform(transform: &Transform) -> Self {
+        Self {
+            pos: transform.translation.xy(),
+            angle: transform.rotation.to_euler(EulerRot::XYZ).2,
+        }
+    }
+
+    pub fn transform(&self, z: f32) -> Transform {
+        Transform::from_translation(self.pos.extend(z))
+            .with_rotation(Quat::from_rotation_z(self.angle))
+    }
+
+    fn push(&self, bytes: &mut Vec<u8>) {
+        push_vec2(bytes, self.pos);
+        bytes.extend_from_slice(&self.angle.to_le_bytes());
+    }
+
+    fn read(reader: &mut ByteReader) -> io::Result<Self> {
+        Ok(Self { pos: reader.vec2()?, angle: reader.f32()? })
+    }
+}
+
+/// Where the player was on one physics step of a run
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GhostFrame {
+    pub body: Pose,
+    pub arm_l: Pose,
+    pub arm_r: Pose,
+    // Point the web is stuck to, if any
+    pub web: Option<Vec2>,
+}
+
+impl GhostFrame {
+    pub fn hand_l(&self) -> Vec2 {
+        self.arm_l.pos + Vec2::from_angle(self.arm_l.angle).rotate(Vec2::X * 15.0)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GhostRun {
+    pub level_hash: u64,
+    pub frames: Vec<GhostFrame>,
+}
+
+impl GhostRun {
+    pub fn path(level_hash: u64) -> PathBuf {
+        PathBuf::from(GHOST_DIR).join(format!("{:016x}.ghost", level_hash))
+    }
+
+    /// Layout: magic, version, level hash, frame count, frames. All little endian.
+    /// Each frame is three poses, a web flag byte, and the web point only when attached.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(17 + self.frames.len() * 45);
+        bytes.extend_from_slice(GHOST_MAGIC);
+        bytes.push(GHOST_VERSION);
+        bytes.extend_from_slice(&self.level_hash.to_le_bytes());
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+
+        for frame in self.frames.iter() {
+            frame.body.push(&mut bytes);
+            frame.arm_l.push(&mut bytes);
+            frame.arm_r.push(&mut bytes);
+
+            match frame.web {
+                Some(point) => {
+                    bytes.push(1);
+                    push_vec2(&mut bytes, point);
+                },
+                None => bytes.push(0),
+            }
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.take(4)? != GHOST_MAGIC {
+            return Err(invalid_data("not a ghost file"));
+        }
+
+        let version = reader.take(1)?[0];
+        if version != GHOST_VERSION {
+            return Err(invalid_data(&format!("unsupported ghost version {}", version)));
+        }
+
+        let level_hash = reader.u64()?;
+        let num_frames = reader.u32()?;
+        let mut frames = Vec::new();
+        for _ in 0..num_frames {
+            let body = Pose::read(&mut reader)?;
+            let arm_l = Pose::read(&mut reader)?;
+            let arm_r = Pose::read(&mut reader)?;
+            let web = match reader.take(1)?[0] {
+                0 => None,
+                _ => Some(reader.vec2()?),
+            };
+
+            frames.push(GhostFrame { body, arm_l, arm_r, web });
+        }
+
+        Ok(Self { level_hash, frames })
+    }
+
+    pub fn load(level_hash: u64) -> io::Result<Self> {
+        let run = Self::from_bytes(&fs::read(Self::path(level_hash))?)?;
+        if run.level_hash != level_hash {
+            return Err(invalid_data("ghost belongs to a different level"));
+        }
+        Ok(run)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        fs::create_dir_all(GHOST_DIR)?;
+        fs::write(Self::path(self.level_hash), self.to_bytes())
+    }
+
+    /// Moves the ghost of a level to a new hash, see `SaveData::rekey_level`
+    pub fn rekey(old_hash: u64, new_hash: u64) -> io::Result<()> {
+        let mut run = match Self::load(old_hash) {
+            Ok(run) => run,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if Self::path(new_hash).exists() { return Ok(()) }
+
+        run.level_hash = new_hash;
+        run.save()?;
+        fs::remove_file(Self::path(old_hash))
+    }
+}
+
+/// Fastest finished run on the loaded level
+#[derive(Default)]
+pub struct BestGhost {
+    pub run: Option<GhostRun>,
+}
+
+#[derive(Component, Clone, Copy)]
+pub enum GhostPart {
+    Body,
+    ArmL,
+    ArmR,
+    Web,
+}
+
+pub fn spawn_ghost(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let color = Color::rgba(1.0, 1.0, 1.0, GHOST_ALPHA);
+    let parts = [
+        (GhostPart::Body, Some("body_capsule.png"), Vec2::new(40.0, 80.0), false),
+        (GhostPart::ArmL, Some("arm_capsule.png"), Vec2::new(40.0, 10.0), false),
+        (GhostPart::ArmR, Some("arm_capsule.png"), Vec2::new(40.0, 10.0), true),
+        (GhostPart::Web, None, Vec2::ONE, false),
+    ];
+
+    for (part, texture, size, flip_x) in parts {
+        commands.spawn_bundle(SpriteBundle {
+            texture: texture.map(|path| asset_server.load(path)).unwrap_or_default(),
+            sprite: Sprite {
+                color,
+                custom_size: Some(size),
+                flip_x,
+                ..default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..default()
+        }).insert(part);
+    }
+}
+
+pub fn load_best_ghost(
+    level_hash: Res<LevelHash>,
+    mut best: ResMut<BestGhost>,
+) {
+    if !level_hash.is_changed() { return }
+
+    best.run = match GhostRun::load(**level_hash) {
+        Ok(run) => Some(run),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            warn!("Ignoring ghost for level {:016x}: {}", **level_hash, e);
+            None
+        },
+    };
+}
+
+pub fn save_best_ghost(
+    level_hash: Res<LevelHash>,
+    run: Res<CurrentRun>,
+    mut best: ResMut<BestGhost>,
+    mut finished: EventReader<RunFinished>,
+) {
+    for _ in finished.iter() {
+        if best.run.as_ref().is_some_and(|b| b.frames.len() <= run.frames.len()) { continue }
+
+        let ghost = GhostRun { level_hash: **level_hash, frames: run.frames.clone() };
+        if let Err(e) = ghost.save() {
+            warn!("Couldn't save ghost: {}", e);
+        }
+        best.run = Some(ghost);
+    }
+}
+
+pub fn update_ghost(
+    run: Res<CurrentRun>,
+    best: Res<BestGhost>,
+
+    mut parts: Query<(&GhostPart, &mut Transform, &mut Visibility)>,
+) {
+    // Before the run starts the ghost waits at its first frame, after it ends it stays at the goal
+    let frame = best.run.as_ref()
+        .and_then(|ghost| ghost.frames.get(run.frames.len().min(ghost.frames.len().saturating_sub(1))));
+
+    for (part, mut transform, mut visibility) in parts.iter_mut() {
+        let frame = match frame {
+            Some(frame) => frame,
+            None => {
+                visibility.is_visible = false;
+                continue
+            },
+        };
+
+        visibility.is_visible = true;
+        *transform = match part {
+            GhostPart::Body => frame.body.transform(GHOST_Z),
+            GhostPart::ArmL => frame.arm_l.transform(GHOST_Z),
+            GhostPart::ArmR => frame.arm_r.transform(GHOST_Z),
+            GhostPart::Web => match frame.web {
+                Some(point) => Transform::from_pt_to_pt(frame.hand_l().extend(GHOST_Z), point.extend(GHOST_Z), 2.0),
+                None => {
+                    visibility.is_visible = false;
+                    continue
+                },
+            },
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ghost_bytes_round_trip() {
+        let frame = |x: f32, web| GhostFrame {
+            body: Pose { pos: Vec2::new(x, 1.0), angle: 0.5 },
+            arm_l: Pose { pos: Vec2::new(x + 45.0, 20.0), angle: -1.0 },
+            arm_r: Pose { pos: Vec2::new(x - 45.0, 20.0), angle: 3.0 },
+            web,
+        };
+        let run = GhostRun {
+            level_hash: 0x0123456789abcdef,
+            frames: vec![frame(0.0, None), frame(10.0, Some(Vec2::new(365.0, 254.0)))],
+        };
+
+        let bytes = run.to_bytes();
+        assert_eq!(GhostRun::from_bytes(&bytes).unwrap(), run);
+        assert!(GhostRun::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
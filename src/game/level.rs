@@ -1,4 +1,15 @@
-use bevy::prelude::*;
+use std::{fs, path::{Path, PathBuf}};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Serialize, Deserialize};
+
+use crate::{editor::{serde::{SerdeLevel, SaveLoaded}, format::LEVEL_EXTENSIONS, components::ShapeId}, save::SaveProfile};
+
+use super::{player::spawn::Respawn, run::RunFinished};
+
+pub const LEVEL_DIR: &str = "./levels";
+// Played on its own when the level directory is missing or empty
+pub const DEFAULT_LEVEL_PATH: &str = "./level.json";
 
 #[derive(Component)]
 pub struct Level;
@@ -7,6 +18,152 @@ pub struct LevelEntity {
     pub entity: Option<Entity>,
 }
 
+/// Hash of the loaded level's contents, used to key files that belong to one version of a level
+#[derive(Deref, DerefMut, Default)]
+pub struct LevelHash(pub u64);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct LevelRect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl LevelRect {
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+}
+
+/// Overrides the camera's framing while the player is inside `area`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CameraZone {
+    pub area: LevelRect,
+    // Holds the camera still here instead of following the player
+    #[serde(default)]
+    pub focus: Option<Vec2>,
+    // Projection scale, replacing the zoom out from speed
+    #[serde(default)]
+    pub zoom: Option<f32>,
+}
+
+/// Camera metadata stored with the level, kept as a resource while it's loaded
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct LevelCamera {
+    // The view never shows anything outside of this
+    pub bounds: Option<LevelRect>,
+    // The first zone containing the player wins
+    pub zones: Vec<CameraZone>,
+}
+
+impl LevelCamera {
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_none() && self.zones.is_empty()
+    }
+
+    pub fn zone_at(&self, point: Vec2) -> Option<&CameraZone> {
+        self.zones.iter().find(|zone| zone.area.contains(point))
+    }
+}
+
+/// Entity of every shape in the loaded level by id, for anything referring to shapes
+#[derive(Default)]
+pub struct ShapeIndex {
+    entities: HashMap<ShapeId, Entity>,
+}
+
+impl ShapeIndex {
+    pub fn get(&self, id: ShapeId) -> Option<Entity> {
+        self.entities.get(&id).copied()
+    }
+}
+
+pub fn index_shapes(
+    mut index: ResMut<ShapeIndex>,
+    removed: RemovedComponents<ShapeId>,
+    shape_q: Query<(Entity, &ShapeId), Changed<ShapeId>>,
+) {
+    for entity in removed.iter() {
+        index.entities.retain(|_, indexed| *indexed != entity);
+    }
+    for (entity, id) in shape_q.iter() {
+        index.entities.insert(*id, entity);
+    }
+}
+
+/// File the level is loaded from and saved to
+#[derive(Deref, DerefMut)]
+pub struct CurrentLevel(pub PathBuf);
+
+impl Default for CurrentLevel {
+    fn default() -> Self {
+        Self(PathBuf::from(DEFAULT_LEVEL_PATH))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LevelInfo {
+    // File stem, also the key used for unlocks in the save
+    pub name: String,
+    pub path: PathBuf,
+    pub hash: u64,
+    pub collectibles: usize,
+}
+
+/// Every playable level, in the order they unlock
+pub struct LevelList {
+    pub levels: Vec<LevelInfo>,
+}
+
+impl LevelList {
+    pub fn discover() -> Self {
+        let levels = level_paths().into_iter().filter_map(|path| match SerdeLevel::load(&path) {
+            Ok(level) => Some(LevelInfo {
+                name: path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+                hash: level.content_hash(),
+                collectibles: level.collectible_count(),
+                path,
+            }),
+            Err(e) => {
+                warn!("Skipping level {}: {}", path.display(), e);
+                None
+            },
+        }).collect();
+
+        Self { levels }
+    }
+
+    pub fn index_of(&self, path: &Path) -> Option<usize> {
+        self.levels.iter().position(|level| level.path == path)
+    }
+
+    /// The first level is always open, the rest once the level before them is finished
+    pub fn is_unlocked(&self, index: usize, profile: &SaveProfile) -> bool {
+        index == 0 || profile.data.unlocked_levels.contains(&self.levels[index].name)
+    }
+}
+
+/// The level files in `LEVEL_DIR` sorted by name, or just `DEFAULT_LEVEL_PATH`
+pub fn level_paths() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(LEVEL_DIR)
+        .map(|dir| dir.filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| LEVEL_EXTENSIONS.iter().any(|known| ext == *known)))
+            .collect())
+        .unwrap_or_default();
+    paths.sort();
+
+    if paths.is_empty() {
+        paths.push(PathBuf::from(DEFAULT_LEVEL_PATH));
+    }
+    paths
+}
+
+/// Replaces the loaded level with the one at `path` and respawns the player
+pub struct LoadLevel {
+    pub path: PathBuf,
+}
+
 pub fn level_startup(
     mut commands: Commands,
     mut level_entity: ResMut<LevelEntity>,
@@ -16,4 +173,46 @@ pub fn level_startup(
         .insert_bundle(VisibilityBundle::default()).id();
 
     level_entity.entity = Some(entity);
-}
\ No newline at end of file
+}
+
+pub fn load_level(
+    mut commands: Commands,
+    mut events: EventReader<LoadLevel>,
+
+    level_entity: Res<LevelEntity>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut loaded: ResMut<SaveLoaded>,
+    mut respawn: ResMut<Respawn>,
+) {
+    let path = match events.iter().last() {
+        Some(event) => event.path.clone(),
+        None => return,
+    };
+
+    if let Some(level) = level_entity.entity {
+        commands.entity(level).despawn_descendants();
+    }
+
+    **current_level = path;
+    loaded.0 = false;
+    **respawn = true;
+}
+
+pub fn unlock_next_level(
+    mut finished: EventReader<RunFinished>,
+    levels: Res<LevelList>,
+    current_level: Res<CurrentLevel>,
+    mut profile: ResMut<SaveProfile>,
+) {
+    if finished.iter().count() == 0 { return }
+
+    let next = match levels.index_of(&current_level).and_then(|i| levels.levels.get(i + 1)) {
+        Some(next) => next,
+        None => return,
+    };
+
+    if profile.data.unlocked_levels.insert(next.name.clone()) {
+        info!("Unlocked {}", next.name);
+        profile.mark_dirty();
+    }
+}
@@ -0,0 +1,206 @@
+use std::f32::consts::TAU;
+
+use bevy::{prelude::*, math::Vec3Swizzles, utils::HashMap};
+use bevy_rapier2d::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::{editor::components::{EditorShape, ShapeId, ShapeLogic, shape_collision_groups}, util::physics_dt};
+
+use super::player::{components::*, movement::BodyContacts};
+
+/// Carries the signal of a trigger or switch to a door, platform or hazard
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Wire {
+    pub from: ShapeId,
+    pub to: ShapeId,
+    // The target gets the opposite of the signal
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub invert: bool,
+}
+
+/// Wiring stored with the level, kept as a resource while it's loaded
+#[derive(Deref, DerefMut, Clone, Debug, Default)]
+pub struct LevelWires(pub Vec<Wire>);
+
+/// Signal of every trigger and switch in the loaded level. Everything starts off
+/// and goes back to off when the player respawns.
+#[derive(Default)]
+pub struct LogicSignals {
+    on: HashMap<ShapeId, bool>,
+}
+
+impl LogicSignals {
+    pub fn get(&self, source: ShapeId) -> bool {
+        self.on.get(&source).copied().unwrap_or(false)
+    }
+
+    pub fn set(&mut self, source: ShapeId, on: bool) {
+        self.on.insert(source, on);
+    }
+
+    /// A target is on when any of its wires carries a signal
+    pub fn powers(&self, wires: &[Wire], target: ShapeId) -> bool {
+        wires.iter()
+            .filter(|wire| wire.to == target)
+            .any(|wire| self.get(wire.from) != wire.invert)
+    }
+}
+
+/// Where a platform was placed and how long it has moved for
+#[derive(Component)]
+pub struct PlatformMotion {
+    origin: Vec2,
+    time: f32,
+}
+
+/// Puts every signal and platform back when the player (re)spawns
+pub fn reset_logic(
+    mut signals: ResMut<LogicSignals>,
+
+    spawned_q: Query<(), Added<Player>>,
+    mut platform_q: Query<(&mut PlatformMotion, &mut Transform)>,
+) {
+    if spawned_q.is_empty() { return }
+
+    signals.on.clear();
+    for (mut motion, mut transform) in platform_q.iter_mut() {
+        motion.time = 0.0;
+        transform.translation = motion.origin.extend(transform.translation.z);
+    }
+}
+
+pub fn update_signals(
+    mut signals: ResMut<LogicSignals>,
+    mut attached_to: Local<Option<Entity>>,
+
+    player_q: Query<(&Player, &BodyContacts)>,
+    shape_q: Query<(Entity, &ShapeId, &EditorShape)>,
+) {
+    let (player, contacts) = match player_q.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    for (entity, id, _) in shape_q.iter().filter(|(_, _, shape)| shape.logic == Some(ShapeLogic::Trigger)) {
+        signals.set(*id, contacts.contacts.contains(&entity));
+    }
+
+    // Only a web newly sticking flips a switch, not staying stuck to it
+    let hit = player.attached.as_ref().map(|attached| attached.hit_entity);
+    if hit != *attached_to {
+        if let Some((_, id, shape)) = hit.and_then(|entity| shape_q.get(entity).ok()) {
+            if shape.logic == Some(ShapeLogic::Switch) {
+                let on = !signals.get(*id);
+                signals.set(*id, on);
+            }
+        }
+    }
+    *attached_to = hit;
+}
+
+/// Open doors stop colliding and disappear, letting go of any web stuck to them
+pub fn update_doors(
+    signals: Res<LogicSignals>,
+    wires: Res<LevelWires>,
+    mut snapped: EventWriter<WebSnapped>,
+
+    player_q: Query<&Player>,
+    mut door_q: Query<(Entity, &ShapeId, &EditorShape, &mut CollisionGroups, &mut Visibility)>,
+) {
+    for (entity, id, shape, mut groups, mut visibility) in door_q.iter_mut() {
+        if shape.logic != Some(ShapeLogic::Door) { continue }
+
+        let open = signals.powers(&wires, *id);
+        if visibility.is_visible != open { continue }
+
+        visibility.is_visible = !open;
+        *groups = match open {
+            true => CollisionGroups::new(Group::NONE, Group::NONE),
+            false => shape_collision_groups(shape.logic),
+        };
+
+        let attached = player_q.get_single().ok().and_then(|player| player.attached.as_ref());
+        if let Some(attached) = attached.filter(|attached| open && attached.hit_entity == entity) {
+            snapped.send(WebSnapped { point: attached.hit_point });
+        }
+    }
+}
+
+pub fn move_platforms(
+    mut commands: Commands,
+    rapier_config: Res<RapierConfiguration>,
+    signals: Res<LogicSignals>,
+    wires: Res<LevelWires>,
+
+    mut platform_q: Query<(Entity, &ShapeId, &EditorShape, &mut Transform, Option<&mut PlatformMotion>)>,
+) {
+    let dt = physics_dt(&rapier_config);
+
+    for (entity, id, shape, mut transform, motion) in platform_q.iter_mut() {
+        let (offset, period) = match shape.logic {
+            Some(ShapeLogic::Platform { offset, period }) => (offset, period),
+            _ => continue,
+        };
+
+        let mut motion = match motion {
+            Some(motion) => motion,
+            None => {
+                commands.entity(entity).insert(PlatformMotion { origin: transform.translation.xy(), time: 0.0 });
+                continue
+            },
+        };
+
+        if !signals.powers(&wires, *id) { continue }
+
+        motion.time += dt;
+        // Eases in and out at both ends of the trip
+        let t = 0.5 - 0.5 * (TAU * motion.time / period.max(dt)).cos();
+        transform.translation = (motion.origin + offset * t).extend(transform.translation.z);
+    }
+}
+
+pub fn hazard_contacts(
+    signals: Res<LogicSignals>,
+    wires: Res<LevelWires>,
+    mut state_events: EventWriter<PlayerStateChanged>,
+
+    mut player_q: Query<(&mut Player, &BodyContacts)>,
+    shape_q: Query<(&ShapeId, &EditorShape)>,
+) {
+    let (mut player, contacts) = match player_q.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    if matches!(player.state, PlayerState::Dead | PlayerState::Respawning) { return }
+
+    let deadly = contacts.contacts.iter()
+        .filter_map(|entity| shape_q.get(*entity).ok())
+        .any(|(id, shape)| shape.logic == Some(ShapeLogic::Hazard) && !signals.powers(&wires, *id));
+
+    if deadly {
+        player.set_state(PlayerState::Dead, &mut state_events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_follow_any_wire_with_a_signal() {
+        let wire = |from, to, invert| Wire { from: ShapeId(from), to: ShapeId(to), invert };
+        let wires = [wire(1, 10, false), wire(2, 10, false), wire(3, 11, true)];
+        let mut signals = LogicSignals::default();
+
+        assert!(!signals.powers(&wires, ShapeId(10)));
+        // Inverted wires carry a signal while their source is off
+        assert!(signals.powers(&wires, ShapeId(11)));
+        // Nothing is wired to 12
+        assert!(!signals.powers(&wires, ShapeId(12)));
+
+        signals.set(ShapeId(2), true);
+        signals.set(ShapeId(3), true);
+        assert!(signals.powers(&wires, ShapeId(10)));
+        assert!(!signals.powers(&wires, ShapeId(11)));
+    }
+}
@@ -1,25 +1,105 @@
 use bevy::prelude::*;
 
-use crate::editor::serde::editor_load;
+use crate::{editor::{serde::editor_load, components::NextShapeId}, menu::playing};
 
-use self::player::{spawn::{player_spawn, Respawn}, controls::{controls::{player_controls, WebPartEntities}, web_connections::update_web_connections}, camera::player_camera, components::*, respawn_message::{spawn_message, respawn_message}};
+use self::collectible::{CollectibleFound, CollectibleMaterials, collect_items, save_found_collectibles, mark_saved_collectibles, fade_found_collectibles, spawn_collectible_hud, update_collectible_hud};
+use self::feedback::{FeedbackSettings, CameraShake, HitStop, LastBodyVelocity, tick_hit_stop, impact_feedback, attach_feedback, camera_shake};
+use self::level::{LoadLevel, LevelCamera, ShapeIndex, load_level, index_shapes, unlock_next_level};
+use self::logic::{LevelWires, LogicSignals, reset_logic, update_signals, update_doors, move_platforms, hazard_contacts};
+use self::ghost::{BestGhost, spawn_ghost, load_best_ghost, save_best_ghost, update_ghost};
+use self::run::{CurrentRun, RunFinished, track_run};
+use self::timer::{RunComparison, spawn_timer_hud, update_comparison, save_run_times, update_timer_hud};
+use self::player::{spawn::{player_spawn, respawn_dead_player, Respawn}, controls::{controls::{player_controls, WebPartEntities}, web_connections::update_web_connections, wrapping::{toggle_rope_mode, update_wrapping_rope}, web_break::{check_web_break, spawn_snap_particles, update_snap_particles}, aim::{AimSettings, toggle_aim_settings, spawn_aim_preview, aim_preview}, gamepad::{GamepadAim, spawn_aim_reticle, gamepad_aim, update_aim_reticle}}, camera::{CameraSettings, player_camera}, components::*, respawn_message::{spawn_message, respawn_message}, movement::{MovementSettings, track_body_contacts, player_movement}};
 
+pub mod collectible;
+pub mod feedback;
+pub mod ghost;
 pub mod level;
+pub mod logic;
 pub mod player;
+pub mod run;
+pub mod timer;
 
 pub struct GamePlugin;
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
+        // Loading and spawning keep running in menus so the level shows behind them,
+        // everything that moves the game forward only runs while playing
         app.add_system(editor_load)
+            .add_event::<LoadLevel>()
+            .add_system(load_level.before(editor_load))
             .insert_resource(Respawn(true))
-            .add_system(player_spawn)
+            .add_system(player_spawn.after(editor_load))
             .add_startup_system(spawn_message)
-            .insert_resource(FailedShot(false))
+            .add_event::<PlayerStateChanged>()
+            .insert_resource(RopeMode::Chain)
             .insert_resource(WebPartEntities { entities: Vec::new() })
             .insert_resource(FramesRestartKeyHeld(0))
+            .insert_resource(CameraSettings::default())
+            .insert_resource(LevelCamera::default())
+            .init_resource::<NextShapeId>()
+            .init_resource::<ShapeIndex>()
+            .init_resource::<LevelWires>()
+            .init_resource::<LogicSignals>()
+            .add_system_to_stage(CoreStage::PostUpdate, index_shapes)
             .add_system(player_camera)
-            .add_system(player_controls)
+            .insert_resource(FeedbackSettings::default())
+            .insert_resource(CameraShake::default())
+            .insert_resource(HitStop::default())
+            .insert_resource(LastBodyVelocity::default())
+            .add_system_to_stage(CoreStage::First, tick_hit_stop)
+            .add_system_to_stage(CoreStage::PostUpdate, impact_feedback)
+            .add_system_to_stage(CoreStage::PostUpdate, attach_feedback)
+            .add_system(camera_shake.after(player_camera))
+            .add_event::<WebSnapped>()
+            .insert_resource(AimSettings::default())
+            .add_startup_system_to_stage(StartupStage::PostStartup, spawn_aim_preview)
+            .insert_resource(GamepadAim::default())
+            .add_startup_system_to_stage(StartupStage::PostStartup, spawn_aim_reticle)
+            .add_system(update_aim_reticle.after(gamepad_aim))
+            .insert_resource(MovementSettings::default())
             .add_system(respawn_message)
-            .add_system_to_stage(CoreStage::PostUpdate, update_web_connections);
+            .add_system_set(SystemSet::new().with_run_criteria(playing)
+                .with_system(respawn_dead_player)
+                .with_system(gamepad_aim)
+                .with_system(player_controls.after(gamepad_aim))
+                .with_system(toggle_rope_mode)
+                .with_system(update_wrapping_rope.after(player_controls))
+                .with_system(check_web_break.after(update_wrapping_rope))
+                .with_system(spawn_snap_particles.after(check_web_break))
+                .with_system(update_snap_particles)
+                .with_system(toggle_aim_settings)
+                .with_system(aim_preview.after(player_controls).after(gamepad_aim))
+                .with_system(track_body_contacts)
+                .with_system(player_movement.after(track_body_contacts).after(player_controls))
+                .with_system(reset_logic)
+                .with_system(update_signals.after(reset_logic).after(track_body_contacts).after(player_controls))
+                .with_system(update_doors.after(update_signals))
+                .with_system(move_platforms.after(update_signals))
+                .with_system(hazard_contacts.after(update_signals))
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, update_web_connections)
+            .insert_resource(CurrentRun::default())
+            .insert_resource(BestGhost::default())
+            .add_event::<RunFinished>()
+            .add_startup_system(spawn_ghost)
+            .add_system(load_best_ghost.after(editor_load))
+            .add_system_to_stage(CoreStage::PostUpdate, track_run.with_run_criteria(playing))
+            .add_system_to_stage(CoreStage::PostUpdate, save_best_ghost.after(track_run))
+            .add_system_to_stage(CoreStage::PostUpdate, update_ghost.after(track_run))
+            .add_system_to_stage(CoreStage::PostUpdate, unlock_next_level.after(track_run))
+            .insert_resource(RunComparison::default())
+            .add_startup_system(spawn_timer_hud)
+            .add_system_to_stage(CoreStage::PostUpdate, update_comparison.before(track_run))
+            .add_system_to_stage(CoreStage::PostUpdate, save_run_times.after(track_run))
+            .add_system_to_stage(CoreStage::PostUpdate, update_timer_hud.after(save_run_times))
+            .init_resource::<CollectibleMaterials>()
+            .add_event::<CollectibleFound>()
+            .add_system_to_stage(CoreStage::PostUpdate, collect_items.with_run_criteria(playing))
+            .add_system_to_stage(CoreStage::PostUpdate, save_found_collectibles.after(collect_items))
+            .add_system_to_stage(CoreStage::PostUpdate, mark_saved_collectibles)
+            .add_system_to_stage(CoreStage::PostUpdate, fade_found_collectibles.after(collect_items).after(mark_saved_collectibles))
+            .add_startup_system(spawn_collectible_hud)
+            .add_system_to_stage(CoreStage::PostUpdate, update_collectible_hud.after(fade_found_collectibles));
     }
-}
\ No newline at end of file
+}
@@ -1,22 +1,146 @@
+use std::ops::{Add, Sub, Mul};
+
 use bevy::{prelude::*, math::Vec3Swizzles};
+use bevy_rapier2d::prelude::Velocity;
+
+use crate::util::{Cursor, MainCamera};
+use crate::game::{Player, level::LevelCamera};
+
+pub struct CameraSettings {
+    // Roughly how long the camera takes to catch up, in seconds
+    pub smooth_time: f32,
+    pub zoom_smooth_time: f32,
+    // The camera leads by where the body will be this many seconds from now
+    pub look_ahead_time: f32,
+    pub max_look_ahead: f32,
+    // Share of the cursor's offset from the center the camera leans towards
+    pub cursor_lead: f32,
+    // Zooming out starts above this speed and is full at `full_zoom_speed`
+    pub zoom_speed: f32,
+    pub full_zoom_speed: f32,
+    pub max_zoom: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            smooth_time: 0.12,
+            zoom_smooth_time: 0.5,
+            look_ahead_time: 0.2,
+            max_look_ahead: 300.0,
+            cursor_lead: 0.15,
+            zoom_speed: 800.0,
+            full_zoom_speed: 2000.0,
+            max_zoom: 1.4,
+        }
+    }
+}
+
+/// Smoothing state of the main camera
+#[derive(Component, Default)]
+pub struct FollowCamera {
+    pub velocity: Vec2,
+    pub zoom_velocity: f32,
+    // Shake added on top of the followed position, left out of the smoothing
+    pub shake_offset: Vec2,
+}
 
-use crate::util::{EntityQuery, Cursor};
+/// Critically damped spring towards `target`, the same at any framerate.
+/// `velocity` carries over between calls.
+pub fn smooth_damp<T>(current: T, target: T, velocity: &mut T, smooth_time: f32, dt: f32) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    let omega = 2.0 / smooth_time.max(0.0001);
+    let x = omega * dt;
+    let decay = 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x);
 
-use crate::game::Player;
+    let change = current - target;
+    let temp = (*velocity + change * omega) * dt;
+    *velocity = (*velocity - temp * omega) * decay;
+    target + (change + temp) * decay
+}
+
+/// Keeps a view of `half_size` inside `bounds`, centering it when the bounds are smaller
+fn clamp_to_bounds(center: Vec2, half_size: Vec2, bounds: &LevelCamera) -> Vec2 {
+    let bounds = match &bounds.bounds {
+        Some(bounds) => bounds,
+        None => return center,
+    };
+
+    let min = bounds.min + half_size;
+    let max = bounds.max - half_size;
+    Vec2::select(min.cmple(max), center.clamp(min.min(max), max.max(min)), (bounds.min + bounds.max) / 2.0)
+}
 
 pub fn player_camera(
-    camera_q: EntityQuery<Camera>,
-    player_q: Query<&Player>,
-    
+    time: Res<Time>,
+    settings: Res<CameraSettings>,
+    level_camera: Res<LevelCamera>,
     cursor: Res<Cursor>,
 
-    mut transform_query: Query<&mut Transform>,
+    player_q: Query<&Player>,
+    body_q: Query<(&Transform, &Velocity), Without<MainCamera>>,
+    mut camera_q: Query<(&mut Transform, &mut OrthographicProjection, &mut FollowCamera), With<MainCamera>>,
 ) {
-    if player_q.is_empty() { return }
+    let player = match player_q.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let (body_transform, body_velocity) = match body_q.get(player.body) {
+        Ok(body) => body,
+        Err(_) => return,
+    };
+    let (mut cam_transform, mut projection, mut follow) = match camera_q.get_single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+
+    let dt = time.delta_seconds();
+    let body_pos = body_transform.translation.xy();
+    let cam_pos = cam_transform.translation.xy() - follow.shake_offset;
+    let speed = body_velocity.linvel.length();
+
+    let look_ahead = (body_velocity.linvel * settings.look_ahead_time).clamp_length_max(settings.max_look_ahead);
+    let cursor_lead = (cursor.world_pos - cam_pos) * settings.cursor_lead;
+    let follow_pos = body_pos + look_ahead + cursor_lead;
+
+    let speed_t = ((speed - settings.zoom_speed) / (settings.full_zoom_speed - settings.zoom_speed)).clamp(0.0, 1.0);
+    let speed_zoom = 1.0 + (settings.max_zoom - 1.0) * speed_t;
+
+    let zone = level_camera.zone_at(body_pos);
+    let goal_pos = zone.and_then(|zone| zone.focus).unwrap_or(follow_pos);
+    let goal_zoom = zone.and_then(|zone| zone.zoom).unwrap_or(speed_zoom);
+
+    let zoom = smooth_damp(projection.scale, goal_zoom, &mut follow.zoom_velocity, settings.zoom_smooth_time, dt);
+    projection.scale = zoom;
+
+    let half_size = Vec2::new(projection.right - projection.left, projection.top - projection.bottom) * zoom / 2.0;
+    let goal_pos = clamp_to_bounds(goal_pos, half_size, &level_camera);
+    let new_pos = smooth_damp(cam_pos, goal_pos, &mut follow.velocity, settings.smooth_time, dt);
+
+    // Clamped again since zooming out can push the current view past the edges
+    let new_pos = clamp_to_bounds(new_pos, half_size, &level_camera);
+    cam_transform.translation = (new_pos + follow.shake_offset).extend(cam_transform.translation.z);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let body_pos = transform_query.get(player_q.single().body).unwrap().translation.xy();
-    let goal_pos = body_pos + (cursor.pos * 0.15);
+    #[test]
+    fn smooth_damp_settles_the_same_at_any_framerate() {
+        let settle = |steps: u32| {
+            let mut velocity = 0.0;
+            let mut value = 0.0;
+            for _ in 0..steps {
+                value = smooth_damp(value, 100.0, &mut velocity, 0.2, 1.0 / steps as f32);
+            }
+            value
+        };
 
-    let mut cam_transform = transform_query.get_mut(camera_q.single()).unwrap();
-    cam_transform.translation = cam_transform.translation.lerp(goal_pos.extend(0.0), 0.5);
-}
\ No newline at end of file
+        let (slow, fast) = (settle(30), settle(240));
+        assert!((slow - fast).abs() < 0.5, "{} vs {}", slow, fast);
+        assert!(fast > 99.0 && fast <= 100.0);
+    }
+}
@@ -1,4 +1,5 @@
 use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
 
 #[derive(Component)]
 pub struct Player {
@@ -7,16 +8,112 @@ pub struct Player {
     pub arm_l: Entity,
     pub eyes: [Entity; 2],
     pub attached: Option<Attached>,
+    pub state: PlayerState,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlayerState {
+    Idle,
+    Shooting,
+    Retracting,
+    Attached,
+    Zipping,
+    Dead,
+    Respawning,
+}
+
+/// Sent whenever the player moves between two different states
+pub struct PlayerStateChanged {
+    pub from: PlayerState,
+    pub to: PlayerState,
+}
+
+impl Player {
+    pub fn set_state(&mut self, to: PlayerState, events: &mut EventWriter<PlayerStateChanged>) {
+        if self.state == to { return }
+
+        events.send(PlayerStateChanged { from: self.state, to });
+        self.state = to;
+    }
 }
 
 #[derive(Component, Debug)]
 pub struct Attached {
+    pub hit_entity: Entity,
     pub hit_point: Vec2,
     pub start_cursor_pos: Vec2,
     pub num_segments: u32,
     pub min_length: f32,
+    // Multiple of the rest length the web snaps at, less for longer webs
+    pub max_stretch: f32,
+    pub time_attached: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RopeMode {
+    Chain,
+    Wrapping,
+}
+
+impl RopeMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            RopeMode::Chain => RopeMode::Wrapping,
+            RopeMode::Wrapping => RopeMode::Chain,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct WrappingRope {
+    pub anchor: Vec2,
+    pub bends: Vec<RopeBend>,
+    pub length: f32,
+}
+
+impl WrappingRope {
+    pub fn new(anchor: Vec2, length: f32) -> Self {
+        Self { anchor, bends: Vec::new(), length }
+    }
+
+    pub fn pivot(&self) -> Vec2 {
+        self.bends.last().map(|b| b.point).unwrap_or(self.anchor)
+    }
+
+    /// Length of rope already used up between the anchor and the current pivot
+    pub fn wrapped_length(&self) -> f32 {
+        let mut prev = self.anchor;
+        let mut total = 0.0;
+        for bend in self.bends.iter() {
+            total += prev.distance(bend.point);
+            prev = bend.point;
+        }
+        total
+    }
+
+    pub fn free_length(&self) -> f32 {
+        self.length - self.wrapped_length()
+    }
+
+    pub fn points(&self, hand: Vec2) -> Vec<Vec2> {
+        let mut points = Vec::with_capacity(self.bends.len() + 2);
+        points.push(self.anchor);
+        points.extend(self.bends.iter().map(|b| b.point));
+        points.push(hand);
+        points
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RopeBend {
+    pub point: Vec2,
+    // Which way the rope turned around this point, used to know when to unwrap
+    pub side: f32,
 }
 
+#[derive(Component)]
+pub struct WrappingRopeSegment;
+
 #[derive(Component)]
 pub struct ShootingWeb {
     pub ray_norm: Vec2,
@@ -25,12 +122,25 @@ pub struct ShootingWeb {
     pub steps: u32,
 }
 
-#[derive(Deref, DerefMut)]
-pub struct FailedShot(pub bool);
-
 #[derive(Component)]
 pub struct WebShotVisual;
 
+#[derive(Component)]
+pub struct AimPreview;
+
+#[derive(Component)]
+pub struct AimPreviewLine;
+
+pub struct WebSnapped {
+    pub point: Vec2,
+}
+
+#[derive(Component)]
+pub struct WebSnapParticle {
+    pub velocity: Vec2,
+    pub life: f32,
+}
+
 #[derive(Component)]
 pub struct WebPart;
 
@@ -44,4 +154,4 @@ pub struct WebPartConnection {
 pub struct FramesRestartKeyHeld(pub u32);
 
 #[derive(Component)]
-pub struct RespawnMessage;
\ No newline at end of file
+pub struct RespawnMessage;
@@ -0,0 +1,168 @@
+use bevy::{prelude::*, math::Vec3Swizzles, sprite::MaterialMesh2dBundle};
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::geometry::Group;
+
+use crate::{input::Action, util::{PreloadedAssets, ExtraTransformMethods, DEGREES}, editor::components::EditorShape, game::player::components::*};
+
+use super::controls::MAX_WEB_LENGTH;
+
+pub const AIM_ASSIST_STEP: f32 = 1.0 * DEGREES;
+
+type AimPreviewQuery<'w, 's> = Query<'w, 's, (
+    &'static mut Transform,
+    &'static mut Visibility,
+    &'static Handle<ColorMaterial>,
+    Option<&'static AimPreviewLine>,
+), With<AimPreview>>;
+
+pub struct AimSettings {
+    pub preview: bool,
+    pub assist: bool,
+    // Half-angle of the cone searched for stickable surfaces
+    pub assist_angle: f32,
+    pub assist_range: f32,
+}
+
+impl Default for AimSettings {
+    fn default() -> Self {
+        Self {
+            preview: true,
+            assist: false,
+            assist_angle: 10.0 * DEGREES,
+            assist_range: MAX_WEB_LENGTH,
+        }
+    }
+}
+
+pub fn cast_aim_ray(
+    rapier_context: &RapierContext,
+    origin: Vec2,
+    dir: Vec2,
+    max_toi: f32,
+) -> Option<(Entity, RayIntersection)> {
+    rapier_context.cast_ray_and_get_normal(
+        origin,
+        dir,
+        max_toi,
+        true,
+        QueryFilter::default().groups(
+            InteractionGroups::new(Group::ALL, Group::from_bits_truncate(0b1))
+        ),
+    )
+}
+
+/// Direction a web fired from `origin` at `target` should travel, snapped to the
+/// nearest stickable surface inside the assist cone when aim assist is on.
+pub fn aim_direction(
+    rapier_context: &RapierContext,
+    settings: &AimSettings,
+    origin: Vec2,
+    target: Vec2,
+    is_stickable: impl Fn(Entity) -> bool,
+) -> Vec2 {
+    let dir = (target - origin).normalize();
+    if !settings.assist { return dir }
+
+    let steps = (settings.assist_angle / AIM_ASSIST_STEP).ceil() as i32;
+    for i in 0..=steps {
+        for sign in [1.0, -1.0] {
+            let test_dir = Vec2::from_angle(sign * i as f32 * AIM_ASSIST_STEP).rotate(dir);
+
+            if let Some((entity, _)) = cast_aim_ray(rapier_context, origin, test_dir, settings.assist_range) {
+                if is_stickable(entity) {
+                    return test_dir
+                }
+            }
+
+            if i == 0 { break }
+        }
+    }
+
+    dir
+}
+
+pub fn toggle_aim_settings(
+    actions: Res<Input<Action>>,
+    mut settings: ResMut<AimSettings>,
+) {
+    if actions.just_pressed(Action::ToggleAimPreview) {
+        settings.preview = !settings.preview;
+    }
+
+    if actions.just_pressed(Action::ToggleAimAssist) {
+        settings.assist = !settings.assist;
+        info!("Aim assist: {}", settings.assist);
+    }
+}
+
+pub fn spawn_aim_preview(
+    mut commands: Commands,
+
+    preload: Res<PreloadedAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.spawn_bundle(MaterialMesh2dBundle {
+        mesh: preload.meshes.get("circle 4").unwrap().clone(),
+        material: materials.add(ColorMaterial::from(Color::WHITE)),
+        transform: Transform::from_xyz(0.0, 0.0, 2.0),
+        visibility: Visibility { is_visible: false },
+        ..default()
+    }).insert(AimPreview);
+
+    commands.spawn_bundle(MaterialMesh2dBundle {
+        mesh: preload.meshes.get("box 1").unwrap().clone(),
+        material: materials.add(ColorMaterial::from(Color::WHITE)),
+        visibility: Visibility { is_visible: false },
+        ..default()
+    }).insert_bundle((AimPreview, AimPreviewLine));
+}
+
+pub fn aim_preview(
+    rapier_context: Res<RapierContext>,
+    cursor: Res<crate::util::Cursor>,
+    settings: Res<AimSettings>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+
+    player_q: Query<&Player>,
+    transform_q: Query<(&Transform, Option<&EditorShape>), Without<AimPreview>>,
+    mut preview_q: AimPreviewQuery,
+) {
+    let player = match player_q.get_single() {
+        Ok(player) if settings.preview && player.attached.is_none() => player,
+        _ => {
+            preview_q.for_each_mut(|(_, mut visibility, _, _)| visibility.is_visible = false);
+            return
+        },
+    };
+
+    let (arm_l_transform, _) = transform_q.get(player.arm_l).unwrap();
+    let hand_l_position = (arm_l_transform.rotation.mul_vec3(Vec3::X * 15.0) + arm_l_transform.translation).xy();
+
+    let is_stickable = |e| transform_q.get(e).ok().and_then(|(_, s)| s).is_some_and(|s| s.stickable);
+    let dir = aim_direction(&rapier_context, &settings, hand_l_position, cursor.world_pos, is_stickable);
+    let hit = cast_aim_ray(&rapier_context, hand_l_position, dir, MAX_WEB_LENGTH);
+
+    for (mut transform, mut visibility, material, line) in preview_q.iter_mut() {
+        let (entity, intersection) = match hit {
+            Some(hit) => hit,
+            None => {
+                visibility.is_visible = false;
+                continue
+            },
+        };
+
+        let color = match is_stickable(entity) {
+            true => Color::rgb(0.3, 1.0, 0.3),
+            false => Color::rgb(1.0, 0.3, 0.3),
+        };
+
+        visibility.is_visible = true;
+        if line.is_some() {
+            *transform = Transform::from_pt_to_pt(hand_l_position.extend(1.0), intersection.point.extend(1.0), 2.0);
+            materials.get_mut(material).unwrap().color = *color.clone().set_a(0.3);
+        } else {
+            transform.translation = intersection.point.extend(2.0);
+            materials.get_mut(material).unwrap().color = color;
+        }
+    }
+}
@@ -1,13 +1,14 @@
 use bevy::{prelude::*, math::Vec3Swizzles, sprite::MaterialMesh2dBundle, input::mouse::MouseMotion, ecs::system::SystemParam};
-use bevy_inspector_egui::egui::Key;
 use bevy_rapier2d::prelude::*;
 
-use crate::{util::{Cursor, EntityQuery, PreloadedAssets, DEGREES}, editor::components::EditorShape, game::player::{components::*, spawn::Respawn}};
+use crate::{input::{Action, AnalogInput}, util::{Cursor, EntityQuery, PreloadedAssets, DEGREES}, editor::components::EditorShape, game::player::{components::*, spawn::Respawn}};
 
-use super::raycast::handle_raycast;
+use super::{raycast::handle_raycast, wrapping::MIN_FREE_LENGTH, aim::{AimSettings, aim_direction}};
 
 pub const STEP_LENGTH: f32 = 100.0;
 pub const MAX_WEB_LENGTH: f32 = 1001.0;
+// Reel distance per frame while a reel key is held, in the same units as mouse movement
+pub const KEY_REEL_SPEED: f32 = 24.0;
 
 pub struct WebPartEntities {
     pub entities: Vec<Entity>,
@@ -18,13 +19,16 @@ pub struct PlayerControlsParam<'w, 's> {
     pub commands: Commands<'w, 's>,
  
     pub cursor: Res<'w, Cursor>,
-    pub mouse: Res<'w, Input<MouseButton>>,
-    pub keyboard: Res<'w, Input<KeyCode>>,
+    pub actions: Res<'w, Input<Action>>,
+    pub analog: Res<'w, AnalogInput>,
     pub frames_r_key: ResMut<'w, FramesRestartKeyHeld>,
-    pub failed_shot: ResMut<'w, FailedShot>,
     pub web_connection_entities: ResMut<'w, WebPartEntities>,
+    pub rope_mode: Res<'w, RopeMode>,
+    pub aim_settings: Res<'w, AimSettings>,
  
     pub mouse_motion_events: EventReader<'w, 's, MouseMotion>,
+    pub web_snapped: EventReader<'w, 's, WebSnapped>,
+    pub state_events: EventWriter<'w, 's, PlayerStateChanged>,
  
     pub rapier_context: Res<'w, RapierContext>,
     pub preload: Res<'w, PreloadedAssets>,
@@ -37,10 +41,12 @@ pub struct PlayerControlsQueries<'w, 's> {
     pub web_part_entities: EntityQuery<'w, 's, WebPart>,
     pub web_shot_entities: EntityQuery<'w, 's, WebShotVisual>,
     pub web_connections: EntityQuery<'w, 's, WebPartConnection>,
+    pub wrapping_segments: EntityQuery<'w, 's, WrappingRopeSegment>,
 
     pub transform: Query<'w, 's, &'static mut Transform>,
     pub visibility: Query<'w, 's, &'static mut Visibility>,
     pub shooting: Query<'w, 's, &'static mut ShootingWeb>,
+    pub wrapping_rope: Query<'w, 's, &'static mut WrappingRope>,
     pub impulse_joint: Query<'w, 's, &'static mut ImpulseJoint>,
     pub editor_shape: Query<'w, 's, &'static EditorShape>,
     pub shape_with_joint: Query<'w, 's, Entity, (With<EditorShape>, With<ImpulseJoint>)>,
@@ -52,13 +58,27 @@ pub fn player_controls(
 ) {
     if query.player.is_empty() { return }
     let (player_entity, player) = query.player.single();
+    let state = player.state;
+
+    if matches!(state, PlayerState::Dead | PlayerState::Respawning) {
+        if player.attached.is_some() || query.shooting.contains(player_entity) {
+            release_web(&mut p, &mut query);
+        }
+        return
+    }
 
     let arm_l_transform = query.transform.get(player.arm_l).unwrap();
     let hand_l_position = (arm_l_transform.rotation.mul_vec3(Vec3::X * 15.0) + arm_l_transform.translation).xy();
 
-    if p.mouse.just_pressed(MouseButton::Left) {
+    if p.actions.just_pressed(Action::ShootWeb) {
         let ray_length = STEP_LENGTH;
-        let ray_norm = (p.cursor.world_pos - hand_l_position).normalize();
+        let ray_norm = aim_direction(
+            &p.rapier_context,
+            &p.aim_settings,
+            hand_l_position,
+            p.cursor.world_pos,
+            |e| query.editor_shape.get(e).is_ok_and(|s| s.stickable),
+        );
 
         p.commands.entity(player_entity).insert(ShootingWeb {
             ray_norm,
@@ -77,114 +97,168 @@ pub fn player_controls(
             ..default()
         }).insert(WebShotVisual);
 
-    } else if p.mouse.pressed(MouseButton::Left) {
+        set_player_state(&mut p, &mut query, PlayerState::Shooting);
+
+    } else if p.actions.pressed(Action::ShootWeb) {
         if let Some(attached) = &player.attached {
-            if !p.mouse_motion_events.is_empty() {
+            let key_reel = match (p.actions.pressed(Action::ReelIn), p.actions.pressed(Action::ReelOut)) {
+                (true, false) => KEY_REEL_SPEED,
+                (false, true) => -KEY_REEL_SPEED,
+                _ => p.analog.reel * KEY_REEL_SPEED,
+            };
+
+            if !p.mouse_motion_events.is_empty() || key_reel != 0.0 {
                 let mut delta = Vec2::ZERO;
                 p.mouse_motion_events.iter().for_each(|e| { 
                     delta.x -= e.delta.x; 
                     delta.y += e.delta.y; 
                 });
 
-                let move_dist = (hand_l_position - attached.hit_point).normalize().dot(-delta);
-                let subtract = move_dist / (attached.num_segments * 8) as f32;
-                let min_joint_length = attached.min_length / attached.num_segments as f32;
+                let mut move_dist = (hand_l_position - attached.hit_point).normalize().dot(-delta) + key_reel;
 
-                query.web_part_entities.for_each(|e| {
-                    let mut impulse_joint = query.impulse_joint.get_mut(e).unwrap();
-                    let joint = impulse_joint.data.as_revolute_mut().unwrap();
-                    
-                    let anchor = joint.local_anchor2();
-                    joint.set_local_anchor2((anchor.normalize() * (anchor.length() - subtract)).clamp_length_min(min_joint_length));
-                });
-            }
-        } else {
-            if **p.failed_shot {
-                // Visually retract
-                let web_shot_entity = query.web_shot_entities.iter().next().unwrap();
-                let mut web_shot_transform = query.transform.get_mut(web_shot_entity).unwrap();
-
-                let mut shooting = query.shooting.get_mut(player_entity).unwrap();
-                if shooting.steps > 0 { shooting.steps -= 1; }
-
-                let web_length = shooting.ray_length * shooting.steps as f32;
-                let end_pos = hand_l_position + (shooting.ray_norm * web_length);
-                web_shot_transform.translation = ((hand_l_position + end_pos) * 0.5).extend(0.0);
-                web_shot_transform.scale.y = web_length;
-            } else {
-                // Try to attach
-                let arm_l_transform = query.transform.get(player.arm_l).unwrap();
-                let hand_l_position = (arm_l_transform.rotation.mul_vec3(Vec3::X * 15.0) + arm_l_transform.translation).xy();
-
-                let mut shooting = query.shooting.get_mut(player_entity).unwrap();
-                if (shooting.steps + 1) as f32 * shooting.ray_length <= shooting.max_length {
-                    shooting.steps += 1;
-                    
-                    let goal = (p.cursor.world_pos - hand_l_position).normalize();
-                    let angle_between = Quat::from_rotation_arc(Vec3::Y, shooting.ray_norm.extend(0.0))
-                        .angle_between(Quat::from_rotation_arc(Vec3::Y, goal.extend(0.0)));
-                    let max_rot = 2.0 * DEGREES;
-
-                    if angle_between > max_rot {
-                        let d_tick = shooting.ray_norm.extend(0.0).cross(goal.extend(0.0)).cross(shooting.ray_norm.extend(0.0)).normalize().xy();
-                        let new_ray_norm = (max_rot.cos() * shooting.ray_norm) + (max_rot.sin() * d_tick);
-                        shooting.ray_norm = new_ray_norm;
-                    } else {
-                        shooting.ray_norm = goal;
-                    }
+                if let Ok(mut rope) = query.wrapping_rope.get_mut(player_entity) {
+                    move_dist = (hand_l_position - rope.pivot()).normalize().dot(-delta) + key_reel;
+                    let min_length = attached.min_length.max(rope.wrapped_length() + MIN_FREE_LENGTH);
+                    rope.length = (rope.length - move_dist / 8.0).max(min_length);
                 } else {
-                    **p.failed_shot = true;
+                    let subtract = move_dist / (attached.num_segments * 8) as f32;
+                    let min_joint_length = attached.min_length / attached.num_segments as f32;
+
+                    query.web_part_entities.for_each(|e| {
+                        let mut impulse_joint = query.impulse_joint.get_mut(e).unwrap();
+                        let joint = impulse_joint.data.as_revolute_mut().unwrap();
+                        
+                        let anchor = joint.local_anchor2();
+                        joint.set_local_anchor2((anchor.normalize() * (anchor.length() - subtract)).clamp_length_min(min_joint_length));
+                    });
                 }
 
-                let raycast = p.rapier_context.cast_ray_and_get_normal(
-                    hand_l_position, 
-                    shooting.ray_norm, 
-                    shooting.ray_length * shooting.steps as f32, 
-                    true, 
-                    QueryFilter::default().groups({
-                        // ripperoni
-                        use bevy_rapier2d::rapier::prelude::Group;
-
-                        InteractionGroups::new(
-                            Group::ALL,
-                            Group::from_bits_truncate(0b1)
-                    )}),
-                );
+                let reel_state = match move_dist > 0.0 {
+                    true => PlayerState::Zipping,
+                    false => PlayerState::Attached,
+                };
+                set_player_state(&mut p, &mut query, reel_state);
+            } else if state == PlayerState::Zipping {
+                set_player_state(&mut p, &mut query, PlayerState::Attached);
+            }
+        } else if state == PlayerState::Retracting {
+            // Visually retract
+            let web_shot_entity = query.web_shot_entities.iter().next().unwrap();
+            let mut web_shot_transform = query.transform.get_mut(web_shot_entity).unwrap();
+
+            let mut shooting = query.shooting.get_mut(player_entity).unwrap();
+            if shooting.steps > 0 { shooting.steps -= 1; }
 
-                handle_raycast(
-                    &mut p, 
-                    &mut query, 
-                    raycast,
+            let web_length = shooting.ray_length * shooting.steps as f32;
+            let end_pos = hand_l_position + (shooting.ray_norm * web_length);
+            web_shot_transform.translation = ((hand_l_position + end_pos) * 0.5).extend(0.0);
+            web_shot_transform.scale.y = web_length;
+        } else if state == PlayerState::Shooting {
+            // Try to attach
+            let arm_l_transform = query.transform.get(player.arm_l).unwrap();
+            let hand_l_position = (arm_l_transform.rotation.mul_vec3(Vec3::X * 15.0) + arm_l_transform.translation).xy();
+
+            let mut out_of_range = false;
+            let mut shooting = query.shooting.get_mut(player_entity).unwrap();
+            if (shooting.steps + 1) as f32 * shooting.ray_length <= shooting.max_length {
+                shooting.steps += 1;
+                
+                let goal = aim_direction(
+                    &p.rapier_context,
+                    &p.aim_settings,
                     hand_l_position,
+                    p.cursor.world_pos,
+                    |e| query.editor_shape.get(e).is_ok_and(|s| s.stickable),
                 );
+                let angle_between = Quat::from_rotation_arc(Vec3::Y, shooting.ray_norm.extend(0.0))
+                    .angle_between(Quat::from_rotation_arc(Vec3::Y, goal.extend(0.0)));
+                let max_rot = 2.0 * DEGREES;
+
+                if angle_between > max_rot {
+                    let d_tick = shooting.ray_norm.extend(0.0).cross(goal.extend(0.0)).cross(shooting.ray_norm.extend(0.0)).normalize().xy();
+                    let new_ray_norm = (max_rot.cos() * shooting.ray_norm) + (max_rot.sin() * d_tick);
+                    shooting.ray_norm = new_ray_norm;
+                } else {
+                    shooting.ray_norm = goal;
+                }
+            } else {
+                out_of_range = true;
             }
-        }
-    }
 
-    if p.mouse.just_released(MouseButton::Left) {
-        let (_, mut player) = query.player.single_mut();
+            let raycast = p.rapier_context.cast_ray_and_get_normal(
+                hand_l_position, 
+                shooting.ray_norm, 
+                shooting.ray_length * shooting.steps as f32, 
+                true, 
+                QueryFilter::default().groups({
+                    // ripperoni
+                    use bevy_rapier2d::rapier::prelude::Group;
+
+                    InteractionGroups::new(
+                        Group::ALL,
+                        Group::from_bits_truncate(0b1)
+                )}),
+            );
 
-        **p.failed_shot = false;
-        query.web_part_entities.for_each(|e| { p.commands.entity(e).despawn(); });
-        query.web_shot_entities.for_each(|e| { p.commands.entity(e).despawn(); });
-        query.web_connections.for_each(|e| { p.commands.entity(e).despawn(); });
-        query.shape_with_joint.for_each(|e| { p.commands.entity(e).remove::<ImpulseJoint>(); });
+            if out_of_range {
+                set_player_state(&mut p, &mut query, PlayerState::Retracting);
+            }
 
-        p.web_connection_entities.entities.clear();
+            handle_raycast(
+                &mut p, 
+                &mut query, 
+                raycast,
+                hand_l_position,
+            );
+        }
+    }
 
-        player.attached = None;
-        p.commands.entity(player_entity).remove::<ShootingWeb>();
+    let web_snapped = p.web_snapped.iter().count() > 0;
+    if p.actions.just_released(Action::ShootWeb) || p.actions.just_pressed(Action::Release) || web_snapped {
+        release_web(&mut p, &mut query);
     }
 
-    if p.keyboard.pressed(KeyCode::R) {
+    if p.actions.pressed(Action::Restart) {
         **p.frames_r_key += 1;
         if **p.frames_r_key >= 45 {
+            set_player_state(&mut p, &mut query, PlayerState::Respawning);
             **p.respawn_player = true;
             **p.frames_r_key = 0;
         }
     }
 
-    if p.keyboard.just_released(KeyCode::R) {
+    if p.actions.just_released(Action::Restart) {
         **p.frames_r_key = 0;
     }
-}
\ No newline at end of file
+}
+
+pub fn release_web(
+    p: &mut PlayerControlsParam,
+    query: &mut PlayerControlsQueries,
+) {
+    let (player_entity, mut player) = query.player.single_mut();
+
+    query.web_part_entities.for_each(|e| { p.commands.entity(e).despawn(); });
+    query.web_shot_entities.for_each(|e| { p.commands.entity(e).despawn(); });
+    query.web_connections.for_each(|e| { p.commands.entity(e).despawn(); });
+    query.wrapping_segments.for_each(|e| { p.commands.entity(e).despawn(); });
+    query.shape_with_joint.for_each(|e| { p.commands.entity(e).remove::<ImpulseJoint>(); });
+
+    p.web_connection_entities.entities.clear();
+
+    player.attached = None;
+    p.commands.entity(player_entity).remove::<ShootingWeb>().remove::<WrappingRope>();
+
+    if !matches!(player.state, PlayerState::Dead | PlayerState::Respawning) {
+        player.set_state(PlayerState::Idle, &mut p.state_events);
+    }
+}
+
+fn set_player_state(
+    p: &mut PlayerControlsParam,
+    query: &mut PlayerControlsQueries,
+    to: PlayerState,
+) {
+    let (_, mut player) = query.player.single_mut();
+    player.set_state(to, &mut p.state_events);
+}
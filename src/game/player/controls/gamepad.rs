@@ -0,0 +1,78 @@
+use bevy::{prelude::*, math::Vec3Swizzles, sprite::MaterialMesh2dBundle};
+
+use crate::{input::AnalogInput, util::{Cursor, PreloadedAssets}, game::player::components::*};
+
+// How far from the hand the reticle sits while aiming with a stick
+pub const GAMEPAD_AIM_DISTANCE: f32 = 300.0;
+
+/// Aim from the right stick. It stays in charge until the mouse moves again.
+pub struct GamepadAim {
+    pub active: bool,
+    pub direction: Vec2,
+}
+
+impl Default for GamepadAim {
+    fn default() -> Self {
+        Self { active: false, direction: Vec2::X }
+    }
+}
+
+#[derive(Component)]
+pub struct AimReticle;
+
+pub fn spawn_aim_reticle(
+    mut commands: Commands,
+    preload: Res<PreloadedAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.spawn_bundle(MaterialMesh2dBundle {
+        mesh: preload.meshes.get("circle 4").unwrap().clone(),
+        material: materials.add(ColorMaterial::from(*Color::WHITE.clone().set_a(0.6))),
+        transform: Transform::from_xyz(0.0, 0.0, 3.0).with_scale(Vec3::new(2.0, 2.0, 1.0)),
+        visibility: Visibility { is_visible: false },
+        ..default()
+    }).insert(AimReticle);
+}
+
+/// Points the cursor the right stick's way from the hand, so everything that
+/// aims at the cursor works the same with a gamepad
+pub fn gamepad_aim(
+    analog: Res<AnalogInput>,
+    mut aim: ResMut<GamepadAim>,
+    mut cursor: ResMut<Cursor>,
+
+    player_q: Query<&Player>,
+    transform_q: Query<&Transform>,
+) {
+    if cursor.moved {
+        aim.active = false;
+    }
+
+    if let Some(direction) = analog.aim {
+        aim.active = true;
+        aim.direction = direction;
+    }
+
+    if !aim.active { return }
+
+    let player = match player_q.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    let arm_l_transform = transform_q.get(player.arm_l).unwrap();
+    let hand_l_position = (arm_l_transform.rotation.mul_vec3(Vec3::X * 15.0) + arm_l_transform.translation).xy();
+
+    cursor.world_pos = hand_l_position + aim.direction * GAMEPAD_AIM_DISTANCE;
+}
+
+pub fn update_aim_reticle(
+    aim: Res<GamepadAim>,
+    cursor: Res<Cursor>,
+    mut reticle_q: Query<(&mut Transform, &mut Visibility), With<AimReticle>>,
+) {
+    for (mut transform, mut visibility) in reticle_q.iter_mut() {
+        visibility.is_visible = aim.active;
+        transform.translation = cursor.world_pos.extend(transform.translation.z);
+    }
+}
@@ -1,3 +1,7 @@
 pub mod controls;
 pub mod raycast;
-pub mod web_connections;
\ No newline at end of file
+pub mod web_connections;
+pub mod wrapping;
+pub mod web_break;
+pub mod aim;
+pub mod gamepad;
\ No newline at end of file
@@ -3,7 +3,7 @@ use bevy_rapier2d::prelude::*;
 
 use crate::game::player::components::*;
 
-use super::controls::{PlayerControlsParam, PlayerControlsQueries};
+use super::{controls::{PlayerControlsParam, PlayerControlsQueries}, wrapping::WRAP_MARGIN, web_break::max_stretch};
 
 pub fn handle_raycast(
     p: &mut PlayerControlsParam,
@@ -16,11 +16,31 @@ pub fn handle_raycast(
 
     if let Some((hit_entity, intersection)) = raycast {
         if !query.editor_shape.get(hit_entity).unwrap().stickable {
-            **p.failed_shot = true;
+            player.set_state(PlayerState::Retracting, &mut p.state_events);
+            return
+        }
+
+        player.set_state(PlayerState::Attached, &mut p.state_events);
+
+        if *p.rope_mode == RopeMode::Wrapping {
+            let anchor = intersection.point + (intersection.normal * WRAP_MARGIN);
+            let length = anchor.distance(hand_l_position);
+
+            p.commands.entity(player_entity).insert(WrappingRope::new(anchor, length));
+            query.web_shot_entities.for_each(|e| { p.commands.entity(e).despawn(); });
+
+            player.attached = Some(Attached {
+                hit_entity,
+                hit_point: intersection.point,
+                start_cursor_pos: p.cursor.pos,
+                num_segments: 1,
+                min_length: (intersection.toi - 150.0).max(40.0),
+                max_stretch: max_stretch(length),
+                time_attached: 0.0,
+            });
             return
         }
 
-        **p.failed_shot = false;
         let ball_diameter = 8.0;
         let length = (intersection.point - hand_l_position).length();
         let num_balls = (length / ball_diameter).ceil() as u32;
@@ -95,10 +115,13 @@ pub fn handle_raycast(
         query.web_shot_entities.for_each(|e| { p.commands.entity(e).despawn(); });
 
         player.attached = Some(Attached {
+            hit_entity,
             hit_point: intersection.point,
             start_cursor_pos: p.cursor.pos,
             num_segments: num_balls,
             min_length: (intersection.toi - 150.0).max(40.0),
+            max_stretch: max_stretch(length),
+            time_attached: 0.0,
         });
     } else {
         // Extend line that represents web
@@ -0,0 +1,145 @@
+use std::f32::consts::TAU;
+
+use bevy::{prelude::*, math::Vec3Swizzles, sprite::MaterialMesh2dBundle};
+use bevy_rapier2d::prelude::*;
+
+use crate::{util::{PreloadedAssets, physics_dt}, editor::components::EditorShape, game::player::components::*};
+
+use super::controls::WebPartEntities;
+
+// A web snaps once pulled this far past its rest length, plus whatever fraction of it
+// is left. So short webs take a lot of stretch and long ones snap sooner.
+pub const WEB_STRETCH_ALLOWANCE: f32 = 150.0;
+pub const WEB_MIN_STRETCH: f32 = 1.15;
+pub const WEB_MAX_STRETCH: f32 = 1.6;
+
+pub const SNAP_PARTICLES: u32 = 12;
+pub const SNAP_PARTICLE_LIFE: f32 = 0.4;
+
+/// How far a web of `length` can be stretched, as a multiple of its rest length
+pub fn max_stretch(length: f32) -> f32 {
+    (1.0 + WEB_STRETCH_ALLOWANCE / length.max(1.0)).clamp(WEB_MIN_STRETCH, WEB_MAX_STRETCH)
+}
+
+pub fn check_web_break(
+    rapier_config: Res<RapierConfiguration>,
+    web_parts: Res<WebPartEntities>,
+    mut snapped: EventWriter<WebSnapped>,
+
+    mut player_q: Query<(&mut Player, Option<&WrappingRope>)>,
+    transform_q: Query<&Transform>,
+    joint_q: Query<&ImpulseJoint>,
+    editor_shape_q: Query<&EditorShape>,
+) {
+    if player_q.is_empty() { return }
+    let (mut player, rope) = player_q.single_mut();
+
+    let arm_l_transform = transform_q.get(player.arm_l).unwrap();
+    let hand_l_position = (arm_l_transform.rotation.mul_vec3(Vec3::X * 15.0) + arm_l_transform.translation).xy();
+
+    let attached = match player.attached.as_mut() {
+        Some(attached) => attached,
+        None => return,
+    };
+    attached.time_attached += physics_dt(&rapier_config);
+
+    let stretch = match rope {
+        Some(rope) => {
+            (rope.wrapped_length() + hand_l_position.distance(rope.pivot())) / rope.length
+        },
+        None => {
+            if web_parts.entities.is_empty() { return }
+
+            let mut rest_length = 0.0;
+            let mut length = 0.0;
+            let mut prev = hand_l_position;
+
+            for entity in web_parts.entities.iter() {
+                // Parts spawned this frame don't exist yet
+                let (joint, transform) = match (joint_q.get(*entity), transform_q.get(*entity)) {
+                    (Ok(joint), Ok(transform)) => (joint, transform),
+                    _ => return,
+                };
+
+                rest_length += joint.data.local_anchor2().length();
+
+                let pos = transform.translation.xy();
+                length += prev.distance(pos);
+                prev = pos;
+            }
+
+            length / rest_length.max(1.0)
+        },
+    };
+
+    let expired = match editor_shape_q.get(attached.hit_entity) {
+        Ok(shape) => shape.release_after.is_some_and(|t| attached.time_attached >= t),
+        Err(_) => false,
+    };
+
+    if stretch > attached.max_stretch || expired {
+        let point = match rope {
+            Some(rope) => (rope.pivot() + hand_l_position) * 0.5,
+            None => attached.hit_point,
+        };
+
+        snapped.send(WebSnapped { point });
+    }
+}
+
+pub fn spawn_snap_particles(
+    mut commands: Commands,
+
+    preload: Res<PreloadedAssets>,
+    mut snapped: EventReader<WebSnapped>,
+) {
+    for event in snapped.iter() {
+        for i in 0..SNAP_PARTICLES {
+            let angle = (i as f32 / SNAP_PARTICLES as f32) * TAU + rand::random::<f32>() * 0.5;
+            let speed = 300.0 + rand::random::<f32>() * 300.0;
+
+            commands.spawn_bundle(MaterialMesh2dBundle {
+                mesh: preload.meshes.get("circle 4").unwrap().clone(),
+                material: preload.get_bw_color_handle(Color::WHITE).clone(),
+                transform: Transform::from_translation(event.point.extend(1.0)),
+                ..default()
+            }).insert(WebSnapParticle {
+                velocity: Vec2::from_angle(angle) * speed,
+                life: SNAP_PARTICLE_LIFE,
+            });
+        }
+    }
+}
+
+pub fn update_snap_particles(
+    mut commands: Commands,
+
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut WebSnapParticle, &mut Transform)>,
+) {
+    let dt = time.delta_seconds();
+
+    for (entity, mut particle, mut transform) in particles.iter_mut() {
+        particle.life -= dt;
+        if particle.life <= 0.0 {
+            commands.entity(entity).despawn();
+            continue
+        }
+
+        transform.translation += (particle.velocity * dt).extend(0.0);
+        transform.scale = Vec3::splat(particle.life / SNAP_PARTICLE_LIFE);
+        particle.velocity *= 0.9;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longer_webs_snap_at_less_stretch() {
+        assert_eq!(max_stretch(10.0), WEB_MAX_STRETCH);
+        assert!(max_stretch(400.0) < max_stretch(300.0));
+        assert_eq!(max_stretch(5000.0), WEB_MIN_STRETCH);
+    }
+}
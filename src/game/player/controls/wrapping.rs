@@ -0,0 +1,209 @@
+use bevy::{prelude::*, math::Vec3Swizzles, sprite::MaterialMesh2dBundle};
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::geometry::Group;
+
+use crate::{input::Action, util::{ExtraTransformMethods, PreloadedAssets}, editor::components::{EditorShape, ShapeType}, game::player::components::*};
+
+pub const WRAP_MARGIN: f32 = 4.0;
+pub const MIN_FREE_LENGTH: f32 = 20.0;
+pub const ROPE_STIFFNESS: f32 = 10.0;
+
+pub fn toggle_rope_mode(
+    actions: Res<Input<Action>>,
+    player_q: Query<&Player>,
+    mut rope_mode: ResMut<RopeMode>,
+) {
+    if !actions.just_pressed(Action::ToggleRopeMode) { return }
+    if player_q.iter().any(|p| p.attached.is_some()) { return }
+
+    *rope_mode = rope_mode.toggled();
+    info!("Rope mode: {:?}", *rope_mode);
+}
+
+pub fn update_wrapping_rope(
+    mut commands: Commands,
+
+    rapier_context: Res<RapierContext>,
+    preload: Res<PreloadedAssets>,
+
+    mut player_q: Query<(&Player, Option<&mut WrappingRope>)>,
+    transform_q: Query<(&Transform, Option<&EditorShape>), Without<WrappingRopeSegment>>,
+    mut segment_q: Query<(Entity, &mut Transform), With<WrappingRopeSegment>>,
+    mut velocity_q: Query<&mut Velocity>,
+) {
+    if player_q.is_empty() { return }
+    let (player, rope) = player_q.single_mut();
+
+    let mut rope = match rope {
+        Some(rope) => rope,
+        None => return,
+    };
+
+    let (arm_l_transform, _) = transform_q.get(player.arm_l).unwrap();
+    let hand_l_position = (arm_l_transform.rotation.mul_vec3(Vec3::X * 15.0) + arm_l_transform.translation).xy();
+
+    // Wrap around anything between the hand and the current pivot
+    let pivot = rope.pivot();
+    let to_pivot = pivot - hand_l_position;
+    let dist = to_pivot.length();
+
+    if dist > WRAP_MARGIN * 2.0 {
+        let dir = to_pivot / dist;
+        let raycast = rapier_context.cast_ray(
+            hand_l_position,
+            dir,
+            dist - WRAP_MARGIN * 2.0,
+            true,
+            QueryFilter::default().groups(
+                InteractionGroups::new(Group::ALL, Group::from_bits_truncate(0b1))
+            ),
+        );
+
+        if let Some((hit_entity, toi)) = raycast {
+            if toi > WRAP_MARGIN {
+                let hit_point = hand_l_position + (dir * toi);
+                let (shape_transform, shape) = transform_q.get(hit_entity).unwrap();
+                let point = wrap_point(shape.unwrap(), shape_transform, hit_point, pivot, hand_l_position);
+
+                if point.distance(pivot) > WRAP_MARGIN {
+                    rope.bends.push(RopeBend { point, side: bend_side(pivot, point, hand_l_position) });
+                }
+            }
+        }
+    }
+
+    unwrap_bends(&mut rope, hand_l_position);
+
+    // Keep the hand within the free length of the rope around the pivot
+    let pivot = rope.pivot();
+    let free_length = rope.free_length().max(MIN_FREE_LENGTH);
+    let offset = hand_l_position - pivot;
+    let dist = offset.length();
+
+    if dist > free_length {
+        let dir = offset / dist;
+        let mut velocity = velocity_q.get_mut(player.body).unwrap();
+
+        let outward = velocity.linvel.dot(dir);
+        if outward > 0.0 {
+            velocity.linvel -= dir * outward;
+        }
+        velocity.linvel -= dir * (dist - free_length) * ROPE_STIFFNESS;
+    }
+
+    // Visuals
+    let points = rope.points(hand_l_position);
+    let num_segments = points.len() - 1;
+
+    if segment_q.iter().len() != num_segments {
+        segment_q.for_each(|(e, _)| commands.entity(e).despawn());
+
+        for pts in points.windows(2) {
+            commands.spawn_bundle(MaterialMesh2dBundle {
+                mesh: preload.meshes.get("box 1").unwrap().clone(),
+                material: preload.get_bw_color_handle(Color::WHITE).clone(),
+                transform: Transform::from_pt_to_pt(pts[0].extend(0.0), pts[1].extend(0.0), 6.0),
+                ..default()
+            }).insert(WrappingRopeSegment);
+        }
+    } else {
+        for ((_, mut transform), pts) in segment_q.iter_mut().zip(points.windows(2)) {
+            *transform = Transform::from_pt_to_pt(pts[0].extend(0.0), pts[1].extend(0.0), 6.0);
+        }
+    }
+}
+
+/// Which way the rope turns going from `prev` around `point` to the hand
+fn bend_side(prev: Vec2, point: Vec2, hand: Vec2) -> f32 {
+    (point - prev).perp_dot(hand - point).signum()
+}
+
+/// Unwraps once the rope swings back past the side it was wrapped on
+fn unwrap_bends(rope: &mut WrappingRope, hand: Vec2) {
+    while let Some(bend) = rope.bends.last().copied() {
+        let prev = match rope.bends.len() {
+            1 => rope.anchor,
+            n => rope.bends[n - 2].point,
+        };
+
+        if bend_side(prev, bend.point, hand) * bend.side < 0.0 {
+            rope.bends.pop();
+        } else {
+            break
+        }
+    }
+}
+
+/// Picks the point the rope should bend around after hitting `shape`.
+/// Rectangles bend around the corner closest to the rope, ovals around the hit itself.
+fn wrap_point(
+    shape: &EditorShape,
+    transform: &Transform,
+    hit_point: Vec2,
+    pivot: Vec2,
+    hand: Vec2,
+) -> Vec2 {
+    let center = transform.translation.xy();
+
+    let point = match shape.shape_type {
+        ShapeType::Rectangle => {
+            let half = transform.scale.xy();
+            [Vec2::new(1.0, 1.0), Vec2::new(-1.0, 1.0), Vec2::new(-1.0, -1.0), Vec2::new(1.0, -1.0)]
+                .into_iter()
+                .map(|c| center + transform.rotation.mul_vec3((c * half).extend(0.0)).xy())
+                .min_by(|a, b| {
+                    dist_to_segment(*a, pivot, hand).total_cmp(&dist_to_segment(*b, pivot, hand))
+                })
+                .unwrap()
+        },
+        ShapeType::Oval => hit_point,
+    };
+
+    point + (point - center).normalize_or_zero() * WRAP_MARGIN
+}
+
+fn dist_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+    p.distance(a + ab * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rectangles_bend_around_the_corner_nearest_the_rope() {
+        let shape = EditorShape::new(ShapeType::Rectangle, true);
+        let transform = Transform::from_scale(Vec3::new(50.0, 10.0, 1.0));
+
+        // Rope from above right of the box to below left of it, hitting its top
+        let point = wrap_point(&shape, &transform, Vec2::new(0.0, 10.0), Vec2::new(200.0, 100.0), Vec2::new(-200.0, -20.0));
+        let corner = Vec2::new(-50.0, 10.0);
+        assert!(point.distance(corner + corner.normalize() * WRAP_MARGIN) < 1e-3, "{}", point);
+
+        // Ovals bend at the hit, pushed out from the center
+        let oval = EditorShape::new(ShapeType::Oval, true);
+        let point = wrap_point(&oval, &Transform::from_scale(Vec3::splat(10.0)), Vec2::new(0.0, 10.0), Vec2::ZERO, Vec2::ZERO);
+        assert_eq!(point, Vec2::new(0.0, 10.0 + WRAP_MARGIN));
+    }
+
+    #[test]
+    fn bends_unwrap_when_swinging_back_past_them() {
+        let mut rope = WrappingRope::new(Vec2::new(0.0, 100.0), 500.0);
+        let point = Vec2::new(0.0, 0.0);
+        let hand = Vec2::new(-100.0, 0.0);
+        rope.bends.push(RopeBend { point, side: bend_side(rope.anchor, point, hand) });
+        assert_eq!(rope.wrapped_length(), 100.0);
+        assert_eq!(rope.free_length(), 400.0);
+
+        // Still on the side it wrapped on
+        unwrap_bends(&mut rope, Vec2::new(-100.0, -50.0));
+        assert_eq!(rope.bends.len(), 1);
+
+        // Swung back across the line through the anchor and the bend
+        unwrap_bends(&mut rope, Vec2::new(100.0, -50.0));
+        assert!(rope.bends.is_empty());
+        assert_eq!(rope.pivot(), rope.anchor);
+    }
+}
@@ -2,4 +2,5 @@ pub mod controls;
 pub mod spawn;
 pub mod camera;
 pub mod components;
-pub mod respawn_message;
\ No newline at end of file
+pub mod respawn_message;
+pub mod movement;
\ No newline at end of file
@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{input::Action, editor::components::EditorShape, util::physics_dt};
+
+use super::components::*;
+
+// How steep a surface can be and still count as ground, as the y of its normal
+pub const GROUND_NORMAL_Y: f32 = 0.7;
+pub const WALL_NORMAL_X: f32 = 0.7;
+
+pub struct MovementSettings {
+    pub walk_speed: f32,
+    pub walk_accel: f32,
+    pub air_steer_accel: f32,
+    // Air steering only adds speed until this horizontal speed is reached
+    pub max_air_steer_speed: f32,
+    pub jump_speed: f32,
+    pub wall_kick_speed: f32,
+}
+
+impl Default for MovementSettings {
+    fn default() -> Self {
+        Self {
+            walk_speed: 300.0,
+            walk_accel: 1500.0,
+            air_steer_accel: 400.0,
+            max_air_steer_speed: 500.0,
+            jump_speed: 600.0,
+            wall_kick_speed: 500.0,
+        }
+    }
+}
+
+#[derive(Component, Default)]
+pub struct BodyContacts {
+    // Kept in the order contacts started so replays pick the same ground normal
+    pub contacts: Vec<Entity>,
+    pub ground_normal: Option<Vec2>,
+    pub wall_normal: Option<Vec2>,
+}
+
+impl BodyContacts {
+    pub fn grounded(&self) -> bool {
+        self.ground_normal.is_some()
+    }
+}
+
+pub fn track_body_contacts(
+    rapier_context: Res<RapierContext>,
+    mut collision_events: EventReader<CollisionEvent>,
+
+    mut player_q: Query<(&Player, &mut BodyContacts)>,
+    shape_q: Query<&EditorShape>,
+) {
+    if player_q.is_empty() { return }
+    let (player, mut contacts) = player_q.single_mut();
+
+    for event in collision_events.iter() {
+        match *event {
+            CollisionEvent::Started(e1, e2, _) => {
+                let other = if e1 == player.body { e2 } else if e2 == player.body { e1 } else { continue };
+                if shape_q.contains(other) && !contacts.contacts.contains(&other) {
+                    contacts.contacts.push(other);
+                }
+            },
+            CollisionEvent::Stopped(e1, e2, _) => {
+                let other = if e1 == player.body { e2 } else if e2 == player.body { e1 } else { continue };
+                contacts.contacts.retain(|e| *e != other);
+            },
+        }
+    }
+
+    let mut ground_normal: Option<Vec2> = None;
+    let mut wall_normal: Option<Vec2> = None;
+
+    for other in contacts.contacts.iter() {
+        let pair = match rapier_context.contact_pair(player.body, *other) {
+            Some(pair) => pair,
+            None => continue,
+        };
+
+        for manifold in pair.manifolds() {
+            if manifold.num_points() == 0 { continue }
+
+            // Normal pointing out of the surface, towards the body
+            let raw = manifold.raw.data.normal;
+            let normal = match pair.collider1() == player.body {
+                true => -Vec2::new(raw.x, raw.y),
+                false => Vec2::new(raw.x, raw.y),
+            };
+
+            if normal.y >= GROUND_NORMAL_Y {
+                if ground_normal.is_none_or(|n| normal.y > n.y) {
+                    ground_normal = Some(normal);
+                }
+            } else if normal.x.abs() >= WALL_NORMAL_X {
+                wall_normal = Some(normal);
+            }
+        }
+    }
+
+    contacts.ground_normal = ground_normal;
+    contacts.wall_normal = wall_normal;
+}
+
+pub fn player_movement(
+    rapier_config: Res<RapierConfiguration>,
+    actions: Res<Input<Action>>,
+    settings: Res<MovementSettings>,
+
+    player_q: Query<(&Player, &BodyContacts)>,
+    mut velocity_q: Query<&mut Velocity>,
+) {
+    if player_q.is_empty() { return }
+    let (player, contacts) = player_q.single();
+    let mut velocity = velocity_q.get_mut(player.body).unwrap();
+    let dt = physics_dt(&rapier_config);
+
+    let mut dir = 0.0;
+    if actions.pressed(Action::MoveLeft) { dir -= 1.0; }
+    if actions.pressed(Action::MoveRight) { dir += 1.0; }
+
+    if contacts.grounded() {
+        let target = dir * settings.walk_speed;
+        let max_change = settings.walk_accel * dt;
+        if dir != 0.0 || player.attached.is_none() {
+            velocity.linvel.x += (target - velocity.linvel.x).clamp(-max_change, max_change);
+        }
+    } else if dir != 0.0 && velocity.linvel.x * dir < settings.max_air_steer_speed {
+        velocity.linvel.x += dir * settings.air_steer_accel * dt;
+    }
+
+    if actions.just_pressed(Action::Jump) {
+        if let Some(normal) = contacts.ground_normal {
+            velocity.linvel += normal * settings.jump_speed;
+            velocity.linvel.y = velocity.linvel.y.max(settings.jump_speed);
+        } else if let Some(normal) = contacts.wall_normal {
+            velocity.linvel = Vec2::new(normal.x, 1.0).normalize() * settings.wall_kick_speed
+                + Vec2::new(0.0, velocity.linvel.y.max(0.0));
+        }
+    }
+}
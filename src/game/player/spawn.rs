@@ -1,24 +1,38 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
-use super::components::*;
+use crate::util::physics_dt;
+
+use super::{components::*, movement::BodyContacts};
+
+pub const DEATH_RESPAWN_DELAY: f32 = 1.0;
+// Where the body appears in every level
+pub const SPAWN_POINT: Vec2 = Vec2::ZERO;
 
 #[derive(Deref, DerefMut)]
 pub struct Respawn(pub bool);
 
+// Every piece of a web, despawned along with the player
+type WebEntityQuery<'w, 's> = Query<'w, 's, Entity, Or<(With<WebPart>, With<WebPartConnection>, With<WebShotVisual>, With<WrappingRopeSegment>)>>;
+
 pub fn player_spawn(
     mut commands: Commands,
 
     player_q: Query<(Entity, &Player)>,
-    kill_query: Query<Entity, Or<(With<WebPart>, With<WebPartConnection>, With<WebShotVisual>)>>,
+    kill_query: WebEntityQuery,
 
     asset_server: Res<AssetServer>,
     mut respawn: ResMut<Respawn>,
+    mut state_events: EventWriter<PlayerStateChanged>,
 ) {
     if !**respawn { return }
     **respawn = false;
 
     for (e, p) in player_q.iter() {
+        if p.state != PlayerState::Idle {
+            state_events.send(PlayerStateChanged { from: p.state, to: PlayerState::Idle });
+        }
+
         commands.entity(e).despawn_recursive();
         commands.entity(p.arm_l).despawn();
         commands.entity(p.arm_r).despawn();
@@ -41,7 +55,7 @@ pub fn player_spawn(
             custom_size: Some(Vec2::new(40.0, 80.0)),
             ..default()
         },
-        transform: Transform::from_xyz(0.0, 0.0, -10.0),
+        transform: Transform::from_translation(SPAWN_POINT.extend(-10.0)),
         ..default()
     }).insert_bundle((
         Collider::capsule_y(20.0, 20.0),
@@ -49,6 +63,9 @@ pub fn player_spawn(
         Friction::coefficient(0.1),
         Restitution::coefficient(0.7),
         Velocity::default(),
+        ActiveEvents::COLLISION_EVENTS | ActiveEvents::CONTACT_FORCE_EVENTS,
+        // Every contact is reported, impact feedback picks out the hard ones by speed
+        ContactForceEventThreshold(0.0),
         group,
     )).id();
 
@@ -137,5 +154,31 @@ pub fn player_spawn(
         CollisionGroups::new(Group::NONE, Group::NONE),
     )).id();
 
-    commands.spawn().insert(Player { body, arm_r, arm_l, eyes: [eye_r, eye_l], attached: None });
-}
\ No newline at end of file
+    commands.spawn()
+        .insert(Player { body, arm_r, arm_l, eyes: [eye_r, eye_l], attached: None, state: PlayerState::Idle })
+        .insert(BodyContacts::default());
+}
+
+pub fn respawn_dead_player(
+    rapier_config: Res<RapierConfiguration>,
+    mut dead_time: Local<f32>,
+    mut respawn: ResMut<Respawn>,
+    mut state_events: EventWriter<PlayerStateChanged>,
+
+    mut player_q: Query<&mut Player>,
+) {
+    if player_q.is_empty() { return }
+    let mut player = player_q.single_mut();
+
+    if player.state != PlayerState::Dead {
+        *dead_time = 0.0;
+        return
+    }
+
+    *dead_time += physics_dt(&rapier_config);
+    if *dead_time >= DEATH_RESPAWN_DELAY {
+        player.set_state(PlayerState::Respawning, &mut state_events);
+        **respawn = true;
+        *dead_time = 0.0;
+    }
+}
@@ -0,0 +1,88 @@
+use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::editor::components::EditorShape;
+
+use super::{ghost::{GhostFrame, Pose}, player::{components::*, movement::BodyContacts}};
+
+/// Time at which a checkpoint, or the goal when `checkpoint` is `None`, was first touched
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Split {
+    pub checkpoint: Option<u32>,
+    pub frames: u32,
+}
+
+/// The attempt in progress. It starts with the first web shot after spawning
+/// and finishes when the body touches a goal shape.
+#[derive(Default)]
+pub struct CurrentRun {
+    pub started: bool,
+    pub finished: bool,
+    // One per physics step since the run started
+    pub frames: Vec<GhostFrame>,
+    pub splits: Vec<Split>,
+}
+
+impl CurrentRun {
+    pub fn elapsed_frames(&self) -> u32 {
+        self.frames.len() as u32
+    }
+}
+
+/// Sent on the step the body touches a goal shape
+pub struct RunFinished {
+    pub frames: u32,
+}
+
+pub fn track_run(
+    mut run: ResMut<CurrentRun>,
+    mut state_events: EventReader<PlayerStateChanged>,
+    mut finished: EventWriter<RunFinished>,
+
+    player_q: Query<(&Player, &BodyContacts, Option<&WrappingRope>, ChangeTrackers<Player>)>,
+    transform_q: Query<&Transform>,
+    shape_q: Query<&EditorShape>,
+) {
+    let (player, contacts, rope, player_tracker) = match player_q.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    if player_tracker.is_added() {
+        *run = CurrentRun::default();
+    }
+
+    let shot = state_events.iter().any(|e| e.to == PlayerState::Shooting);
+    if shot && !run.started && !run.finished {
+        run.started = true;
+    }
+
+    if !run.started || run.finished { return }
+
+    let web = player.attached.as_ref().map(|attached| match rope {
+        Some(rope) => rope.pivot(),
+        None => attached.hit_point,
+    });
+
+    run.frames.push(GhostFrame {
+        body: Pose::from_transform(transform_q.get(player.body).unwrap()),
+        arm_l: Pose::from_transform(transform_q.get(player.arm_l).unwrap()),
+        arm_r: Pose::from_transform(transform_q.get(player.arm_r).unwrap()),
+        web,
+    });
+
+    let frames = run.elapsed_frames();
+    for shape in contacts.contacts.iter().filter_map(|e| shape_q.get(*e).ok()) {
+        if let Some(checkpoint) = shape.checkpoint {
+            if !run.splits.iter().any(|s| s.checkpoint == Some(checkpoint)) {
+                run.splits.push(Split { checkpoint: Some(checkpoint), frames });
+            }
+        }
+
+        if shape.goal && !run.finished {
+            run.splits.push(Split { checkpoint: None, frames });
+            run.finished = true;
+            finished.send(RunFinished { frames });
+        }
+    }
+}
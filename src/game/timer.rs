@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::RapierConfiguration;
+use serde::{Serialize, Deserialize};
+
+use crate::{util::physics_dt, save::SaveProfile};
+
+use super::{level::LevelHash, run::{CurrentRun, RunFinished, Split}};
+
+pub const AHEAD_COLOR: Color = Color::rgb(0.3, 0.9, 0.3);
+pub const BEHIND_COLOR: Color = Color::rgb(0.9, 0.3, 0.3);
+pub const BEST_SPLIT_COLOR: Color = Color::rgb(1.0, 0.8, 0.2);
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LevelTimes {
+    // Splits of the fastest finished run
+    pub personal_best: Vec<Split>,
+    // Fastest time each checkpoint was ever reached, across all runs
+    pub best_splits: Vec<Split>,
+}
+
+impl LevelTimes {
+    pub fn pb_split(&self, checkpoint: Option<u32>) -> Option<u32> {
+        self.personal_best.iter().find(|s| s.checkpoint == checkpoint).map(|s| s.frames)
+    }
+
+    pub fn best_split(&self, checkpoint: Option<u32>) -> Option<u32> {
+        self.best_splits.iter().find(|s| s.checkpoint == checkpoint).map(|s| s.frames)
+    }
+
+    /// Takes in the splits of a finished run, returns whether it was a new personal best
+    pub fn record(&mut self, splits: &[Split]) -> bool {
+        for split in splits {
+            match self.best_splits.iter_mut().find(|s| s.checkpoint == split.checkpoint) {
+                Some(best) => best.frames = best.frames.min(split.frames),
+                None => self.best_splits.push(*split),
+            }
+        }
+
+        let total = splits.iter().find(|s| s.checkpoint.is_none()).map(|s| s.frames);
+        let new_pb = match (total, self.pb_split(None)) {
+            (Some(total), Some(pb)) => total < pb,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+
+        if new_pb {
+            self.personal_best = splits.to_vec();
+        }
+        new_pb
+    }
+}
+
+/// Times for every level, keyed by level hash
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SpeedrunTimes {
+    pub levels: BTreeMap<String, LevelTimes>,
+}
+
+impl SpeedrunTimes {
+    pub fn level(&self, level_hash: u64) -> Option<&LevelTimes> {
+        self.levels.get(&format!("{:016x}", level_hash))
+    }
+
+    pub fn level_mut(&mut self, level_hash: u64) -> &mut LevelTimes {
+        self.levels.entry(format!("{:016x}", level_hash)).or_default()
+    }
+}
+
+/// What the current run is compared against, fixed once the run starts
+#[derive(Deref, DerefMut, Default)]
+pub struct RunComparison(pub LevelTimes);
+
+#[derive(Component)]
+pub struct TimerText;
+
+pub fn format_time(seconds: f32) -> String {
+    let minutes = (seconds / 60.0).floor();
+    match minutes > 0.0 {
+        true => format!("{}:{:05.2}", minutes, seconds - minutes * 60.0),
+        false => format!("{:.2}", seconds),
+    }
+}
+
+pub fn spawn_timer_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.spawn_bundle(TextBundle::from_section(
+        "",
+        TextStyle {
+            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+            font_size: 32.0,
+            color: Color::WHITE,
+        },
+    ).with_style(Style {
+        position_type: PositionType::Absolute,
+        position: UiRect {
+            top: Val::Px(20.0),
+            left: Val::Px(20.0),
+            ..default()
+        },
+        ..default()
+    })).insert(TimerText);
+}
+
+pub fn update_comparison(
+    level_hash: Res<LevelHash>,
+    run: Res<CurrentRun>,
+    profile: Res<SaveProfile>,
+    mut comparison: ResMut<RunComparison>,
+) {
+    if run.started { return }
+    **comparison = profile.data.times.level(**level_hash).cloned().unwrap_or_default();
+}
+
+pub fn save_run_times(
+    rapier_config: Res<RapierConfiguration>,
+    level_hash: Res<LevelHash>,
+    run: Res<CurrentRun>,
+    mut profile: ResMut<SaveProfile>,
+    mut finished: EventReader<RunFinished>,
+) {
+    for event in finished.iter() {
+        let time = format_time(event.frames as f32 * physics_dt(&rapier_config));
+
+        match profile.data.times.level_mut(**level_hash).record(&run.splits) {
+            true => info!("Run finished in {}, new personal best", time),
+            false => info!("Run finished in {}", time),
+        }
+        profile.mark_dirty();
+    }
+}
+
+pub fn update_timer_hud(
+    rapier_config: Res<RapierConfiguration>,
+    run: Res<CurrentRun>,
+    comparison: Res<RunComparison>,
+
+    mut text_q: Query<&mut Text, With<TimerText>>,
+) {
+    let mut text = match text_q.get_single_mut() {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    let dt = physics_dt(&rapier_config);
+    let style = text.sections[0].style.clone();
+
+    let section = |value: String, color: Color| TextSection {
+        value,
+        style: TextStyle { color, ..style.clone() },
+    };
+
+    let mut sections = vec![section(format_time(run.elapsed_frames() as f32 * dt), Color::WHITE)];
+
+    for split in run.splits.iter() {
+        let name = match split.checkpoint {
+            Some(checkpoint) => format!("\nCP {}  ", checkpoint + 1),
+            None => "\nGoal  ".to_string(),
+        };
+        sections.push(section(name + &format_time(split.frames as f32 * dt), Color::WHITE));
+
+        let pb = match comparison.pb_split(split.checkpoint) {
+            Some(pb) => pb,
+            None => continue,
+        };
+
+        let delta = (split.frames as f32 - pb as f32) * dt;
+        let color = match comparison.best_split(split.checkpoint) {
+            Some(best) if split.frames < best => BEST_SPLIT_COLOR,
+            _ if delta <= 0.0 => AHEAD_COLOR,
+            _ => BEHIND_COLOR,
+        };
+        let sign = match delta <= 0.0 { true => "-", false => "+" };
+        sections.push(section(format!("  {}{}", sign, format_time(delta.abs())), color));
+    }
+
+    // The final time takes the color of the goal split
+    if run.finished {
+        if let Some(last) = sections.last().filter(|_| sections.len() > 1).map(|s| s.style.color) {
+            sections[0].style.color = last;
+        }
+    }
+
+    text.sections = sections;
+}
@@ -0,0 +1,248 @@
+use bevy::{prelude::*, math::Vec3Swizzles, input::{InputPlugin, InputSystem, mouse::MouseMotion}, asset::AssetPlugin};
+
+use crate::{add_game, input::UpdateActions, menu::AppState, util::Cursor, game::player::components::Player, replay::{ReplayPlugin, ReplayDriver, Recording}, save::SaveProfile};
+
+pub const HEADLESS_FRAMES: u32 = 600;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ScriptAction {
+    PressMouse(MouseButton),
+    ReleaseMouse(MouseButton),
+    PressKey(KeyCode),
+    ReleaseKey(KeyCode),
+    // Moves the cursor to a world position
+    Aim(Vec2),
+    // Raw mouse movement, used to reel webs in and out
+    MouseMotion(Vec2),
+}
+
+/// Input fed to a headless app, one list of actions per frame.
+/// Physics runs one fixed step per frame, so 60 frames is one second of game time.
+#[derive(Default)]
+pub struct InputScript {
+    steps: Vec<(u32, ScriptAction)>,
+    frame: u32,
+}
+
+impl InputScript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn at(mut self, frame: u32, action: ScriptAction) -> Self {
+        self.steps.push((frame, action));
+        self
+    }
+
+    /// Aims at `target` and holds the left mouse button from `frame` for `hold_frames` frames
+    pub fn fire_web(self, frame: u32, target: Vec2, hold_frames: u32) -> Self {
+        self.at(frame, ScriptAction::Aim(target))
+            .at(frame, ScriptAction::PressMouse(MouseButton::Left))
+            .at(frame + hold_frames, ScriptAction::ReleaseMouse(MouseButton::Left))
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+}
+
+pub fn headless_app(script: InputScript) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(AssetPlugin)
+        .add_asset::<Mesh>()
+        .add_asset::<ColorMaterial>()
+        .insert_resource(script)
+        .insert_resource(SaveProfile::in_memory())
+        .add_system_to_stage(CoreStage::PreUpdate, drive_input_script.after(InputSystem).before(UpdateActions));
+
+    add_game(&mut app, AppState::Playing);
+    app
+}
+
+pub fn drive_input_script(
+    mut script: ResMut<InputScript>,
+    mut mouse: ResMut<Input<MouseButton>>,
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut cursor: ResMut<Cursor>,
+    mut mouse_motion: EventWriter<MouseMotion>,
+) {
+    let frame = script.frame;
+
+    for (_, action) in script.steps.iter().filter(|(f, _)| *f == frame) {
+        match *action {
+            ScriptAction::PressMouse(button) => mouse.press(button),
+            ScriptAction::ReleaseMouse(button) => mouse.release(button),
+            ScriptAction::PressKey(key) => keyboard.press(key),
+            ScriptAction::ReleaseKey(key) => keyboard.release(key),
+            ScriptAction::Aim(pos) => {
+                cursor.prev_world_pos = cursor.world_pos;
+                cursor.world_pos = pos;
+            },
+            ScriptAction::MouseMotion(delta) => mouse_motion.send(MouseMotion { delta }),
+        }
+    }
+
+    script.frame += 1;
+}
+
+pub fn run_frames(app: &mut App, frames: u32) {
+    for _ in 0..frames {
+        app.update();
+    }
+}
+
+pub fn body_position(app: &mut App) -> Option<Vec2> {
+    let world = &mut app.world;
+    let body = world.query::<&Player>().iter(world).next()?.body;
+    world.get::<Transform>(body).map(|t| t.translation.xy())
+}
+
+/// Runs `frames` frames with no input, or a whole replay when one is given
+pub fn run_headless(frames: u32, replay: Option<Recording>) {
+    let mut app = headless_app(InputScript::new());
+
+    match replay {
+        Some(recording) => {
+            app.add_plugin(ReplayPlugin { recording });
+            while !app.world.resource::<ReplayDriver>().finished() {
+                app.update();
+            }
+        },
+        None => run_frames(&mut app, frames),
+    }
+
+    match body_position(&mut app) {
+        Some(pos) => println!("Body after {} frames: {:?}", app.world.resource::<InputScript>().frame(), pos),
+        None => println!("No player after {} frames", frames),
+    }
+
+    if let Some(driver) = app.world.get_resource::<ReplayDriver>() {
+        if let Some(frame) = driver.diverged_at {
+            println!("Replay diverged at frame {}", frame);
+            std::process::exit(1);
+        }
+        println!("Replay matched the recording");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::ecs::event::ManualEventReader;
+
+    use crate::{editor::components::EditorShape, game::{level::CurrentLevel, feedback::HitStop, player::{components::{PlayerState, PlayerStateChanged}, spawn::Respawn}}};
+
+    /// Runs `frames` frames, collecting every state change along the way
+    fn state_changes(app: &mut App, frames: u32) -> Vec<(PlayerState, PlayerState)> {
+        let mut reader = ManualEventReader::<PlayerStateChanged>::default();
+        let mut changes = Vec::new();
+        for _ in 0..frames {
+            app.update();
+            let events = app.world.resource::<Events<PlayerStateChanged>>();
+            changes.extend(reader.iter(events).map(|e| (e.from, e.to)));
+        }
+        changes
+    }
+
+    #[test]
+    fn reeling_in_lifts_the_body() {
+        // Oval up and to the right of the spawn point in level.json
+        let target = Vec2::new(365.0, 254.0);
+
+        let mut script = InputScript::new().fire_web(60, target, 120);
+        for frame in 70..170 {
+            script = script.at(frame, ScriptAction::MouseMotion(Vec2::new(-30.0, 30.0)));
+        }
+
+        let mut app = headless_app(script);
+        run_frames(&mut app, 60);
+        let start = body_position(&mut app).unwrap();
+
+        let mut max_height = start.y;
+        for _ in 0..120 {
+            app.update();
+            max_height = max_height.max(body_position(&mut app).unwrap().y);
+        }
+
+        let state = app.world.query::<&Player>().single(&app.world).state;
+        assert_eq!(state, PlayerState::Attached);
+        assert!(max_height > start.y + 50.0, "body only reached {} from {}", max_height, start.y);
+    }
+
+    #[test]
+    fn webs_go_through_shooting_to_attached_and_back_to_idle() {
+        let target = Vec2::new(365.0, 254.0);
+        let mut app = headless_app(InputScript::new().fire_web(60, target, 60));
+
+        let changes = state_changes(&mut app, 150);
+        assert_eq!(changes, [
+            (PlayerState::Idle, PlayerState::Shooting),
+            (PlayerState::Shooting, PlayerState::Attached),
+            (PlayerState::Attached, PlayerState::Idle),
+        ]);
+    }
+
+    #[test]
+    fn webs_that_miss_retract() {
+        let mut app = headless_app(InputScript::new().fire_web(60, Vec2::new(0.0, 2000.0), 120));
+        run_frames(&mut app, 30);
+
+        // Every direction from the spawn point hits something in level.json, so clear the level
+        let shapes: Vec<Entity> = app.world.query_filtered::<Entity, With<EditorShape>>().iter(&app.world).collect();
+        for entity in shapes {
+            app.world.despawn(entity);
+        }
+
+        let changes = state_changes(&mut app, 170);
+        assert_eq!(changes, [
+            (PlayerState::Idle, PlayerState::Shooting),
+            (PlayerState::Shooting, PlayerState::Retracting),
+            (PlayerState::Retracting, PlayerState::Idle),
+        ]);
+    }
+
+    #[test]
+    fn respawning_while_idle_sends_no_event() {
+        let mut app = headless_app(InputScript::new());
+        run_frames(&mut app, 30);
+
+        // Same as the editor's respawn key or a level reload
+        **app.world.resource_mut::<Respawn>() = true;
+        assert!(state_changes(&mut app, 30).is_empty());
+    }
+
+    #[test]
+    fn releasing_during_hit_stop_still_lets_go() {
+        let mut app = headless_app(InputScript::new().fire_web(60, Vec2::new(365.0, 254.0), 1000));
+        let state = |app: &mut App| app.world.query::<&Player>().single(&app.world).state;
+
+        run_frames(&mut app, 60);
+        while state(&mut app) != PlayerState::Attached {
+            app.update();
+        }
+
+        // Let go on the first frame frozen by sticking
+        let script = std::mem::take(&mut *app.world.resource_mut::<InputScript>());
+        let frame = script.frame();
+        app.insert_resource(script.at(frame, ScriptAction::ReleaseMouse(MouseButton::Left)));
+        app.update();
+        assert!(app.world.resource::<HitStop>().is_frozen());
+
+        run_frames(&mut app, 30);
+        assert_eq!(state(&mut app), PlayerState::Idle);
+    }
+
+    #[test]
+    fn missing_levels_load_empty() {
+        let mut app = headless_app(InputScript::new());
+        app.insert_resource(CurrentLevel(std::env::temp_dir().join("spiderman_missing_level.json")));
+        run_frames(&mut app, 5);
+
+        assert_eq!(app.world.query::<&EditorShape>().iter(&app.world).count(), 0);
+    }
+}
@@ -0,0 +1,513 @@
+use std::{collections::BTreeMap, marker::PhantomData};
+
+use bevy::{prelude::*, input::InputSystem, ecs::system::SystemParam};
+use serde::{Serialize, Deserialize};
+
+use crate::{menu::AppState, game::feedback::HitStop};
+
+/// Something the player or level editor can do, read through `Input<Action>`
+/// instead of checking keys directly so every control can be rebound
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    ShootWeb,
+    Release,
+    ReelIn,
+    ReelOut,
+    Restart,
+    ToggleRopeMode,
+    ToggleAimPreview,
+    ToggleAimAssist,
+    Pause,
+
+    SpawnRectangle,
+    SpawnOval,
+    SpawnCollectible,
+    RespawnPlayer,
+    SaveLevel,
+    Select,
+    BoxSelect,
+    // Held to add to the selection and for finer adjustments
+    Modifier,
+    Pan,
+    Taller,
+    Shorter,
+    Wider,
+    Narrower,
+    RotateClockwise,
+    RotateCounterClockwise,
+    ToggleStickable,
+    CycleReleaseAfter,
+    ToggleGoal,
+    ToggleCheckpoint,
+    NudgeUp,
+    NudgeDown,
+    NudgeLeft,
+    NudgeRight,
+    Copy,
+    Paste,
+    DeleteSelection,
+    CycleLogic,
+    // Wires the selection to the hovered shape, inverts that wire, then removes it
+    CycleWire,
+}
+
+impl Action {
+    pub const GAME: [Action; 12] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::ShootWeb,
+        Action::Release,
+        Action::ReelIn,
+        Action::ReelOut,
+        Action::Restart,
+        Action::ToggleRopeMode,
+        Action::ToggleAimPreview,
+        Action::ToggleAimAssist,
+        Action::Pause,
+    ];
+
+    pub const EDITOR: [Action; 28] = [
+        Action::SpawnRectangle,
+        Action::SpawnOval,
+        Action::SpawnCollectible,
+        Action::RespawnPlayer,
+        Action::SaveLevel,
+        Action::Select,
+        Action::BoxSelect,
+        Action::Modifier,
+        Action::Pan,
+        Action::Taller,
+        Action::Shorter,
+        Action::Wider,
+        Action::Narrower,
+        Action::RotateClockwise,
+        Action::RotateCounterClockwise,
+        Action::ToggleStickable,
+        Action::CycleReleaseAfter,
+        Action::ToggleGoal,
+        Action::ToggleCheckpoint,
+        Action::NudgeUp,
+        Action::NudgeDown,
+        Action::NudgeLeft,
+        Action::NudgeRight,
+        Action::Copy,
+        Action::Paste,
+        Action::DeleteSelection,
+        Action::CycleLogic,
+        Action::CycleWire,
+    ];
+
+    pub fn all() -> impl Iterator<Item = Action> {
+        Self::GAME.into_iter().chain(Self::EDITOR)
+    }
+
+    /// Game and editor actions are never active together, so they may share inputs
+    pub fn is_editor(self) -> bool {
+        Self::EDITOR.contains(&self)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Jump => "Jump",
+            Action::ShootWeb => "Shoot web",
+            Action::Release => "Release web",
+            Action::ReelIn => "Reel in",
+            Action::ReelOut => "Reel out",
+            Action::Restart => "Restart (hold)",
+            Action::ToggleRopeMode => "Rope mode",
+            Action::ToggleAimPreview => "Aim preview",
+            Action::ToggleAimAssist => "Aim assist",
+            Action::Pause => "Pause",
+            Action::SpawnRectangle => "Spawn rectangle",
+            Action::SpawnOval => "Spawn oval",
+            Action::SpawnCollectible => "Spawn collectible",
+            Action::RespawnPlayer => "Respawn player",
+            Action::SaveLevel => "Save level",
+            Action::Select => "Select and drag",
+            Action::BoxSelect => "Box select",
+            Action::Modifier => "Modifier",
+            Action::Pan => "Pan camera",
+            Action::Taller => "Taller",
+            Action::Shorter => "Shorter",
+            Action::Wider => "Wider",
+            Action::Narrower => "Narrower",
+            Action::RotateClockwise => "Rotate clockwise",
+            Action::RotateCounterClockwise => "Rotate counter-clockwise",
+            Action::ToggleStickable => "Toggle stickable",
+            Action::CycleReleaseAfter => "Cycle release time",
+            Action::ToggleGoal => "Toggle goal",
+            Action::ToggleCheckpoint => "Toggle checkpoint",
+            Action::NudgeUp => "Nudge up",
+            Action::NudgeDown => "Nudge down",
+            Action::NudgeLeft => "Nudge left",
+            Action::NudgeRight => "Nudge right",
+            Action::Copy => "Copy selection",
+            Action::Paste => "Paste",
+            Action::DeleteSelection => "Delete selection",
+            Action::CycleLogic => "Cycle logic role",
+            Action::CycleWire => "Cycle wire to hovered",
+        }
+    }
+
+    pub fn default_binding(self) -> Binding {
+        use Binding::{Key, Mouse};
+
+        match self {
+            Action::MoveLeft => Key(KeyCode::A),
+            Action::MoveRight => Key(KeyCode::D),
+            Action::Jump => Key(KeyCode::Space),
+            Action::ShootWeb => Mouse(MouseButton::Left),
+            Action::Release => Mouse(MouseButton::Right),
+            Action::ReelIn => Key(KeyCode::W),
+            Action::ReelOut => Key(KeyCode::S),
+            Action::Restart => Key(KeyCode::R),
+            Action::ToggleRopeMode => Key(KeyCode::T),
+            Action::ToggleAimPreview => Key(KeyCode::V),
+            Action::ToggleAimAssist => Key(KeyCode::B),
+            Action::Pause => Key(KeyCode::Escape),
+            Action::SpawnRectangle => Key(KeyCode::R),
+            Action::SpawnOval => Key(KeyCode::C),
+            Action::SpawnCollectible => Key(KeyCode::O),
+            Action::RespawnPlayer => Key(KeyCode::K),
+            Action::SaveLevel => Key(KeyCode::P),
+            Action::Select => Mouse(MouseButton::Left),
+            Action::BoxSelect => Mouse(MouseButton::Right),
+            Action::Modifier => Key(KeyCode::LShift),
+            Action::Pan => Mouse(MouseButton::Middle),
+            Action::Taller => Key(KeyCode::Up),
+            Action::Shorter => Key(KeyCode::Down),
+            Action::Wider => Key(KeyCode::Right),
+            Action::Narrower => Key(KeyCode::Left),
+            Action::RotateClockwise => Key(KeyCode::E),
+            Action::RotateCounterClockwise => Key(KeyCode::Q),
+            Action::ToggleStickable => Key(KeyCode::X),
+            Action::CycleReleaseAfter => Key(KeyCode::Z),
+            Action::ToggleGoal => Key(KeyCode::G),
+            Action::ToggleCheckpoint => Key(KeyCode::H),
+            Action::NudgeUp => Key(KeyCode::W),
+            Action::NudgeDown => Key(KeyCode::S),
+            Action::NudgeLeft => Key(KeyCode::A),
+            Action::NudgeRight => Key(KeyCode::D),
+            Action::Copy => Key(KeyCode::Y),
+            Action::Paste => Key(KeyCode::U),
+            Action::DeleteSelection => Key(KeyCode::Delete),
+            Action::CycleLogic => Key(KeyCode::L),
+            Action::CycleWire => Key(KeyCode::J),
+        }
+    }
+
+    pub fn default_pad_binding(self) -> Option<Binding> {
+        use GamepadButtonType::*;
+
+        let button = match self {
+            Action::MoveLeft => DPadLeft,
+            Action::MoveRight => DPadRight,
+            Action::Jump => South,
+            Action::ShootWeb => RightTrigger2,
+            Action::Release => LeftTrigger2,
+            Action::ReelIn => RightTrigger,
+            Action::ReelOut => LeftTrigger,
+            Action::Restart => Select,
+            Action::ToggleRopeMode => North,
+            Action::ToggleAimAssist => West,
+            Action::Pause => Start,
+            _ => return None,
+        };
+        Some(Binding::Pad(button))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    // Any connected gamepad
+    Pad(GamepadButtonType),
+}
+
+impl Binding {
+    /// Gamepad buttons are bound in a second slot, so every action can have one of each
+    pub fn is_pad(self) -> bool {
+        matches!(self, Binding::Pad(_))
+    }
+
+    // Replays only drive keyboard and mouse bindings
+    pub fn press(self, keyboard: &mut Input<KeyCode>, mouse: &mut Input<MouseButton>) {
+        match self {
+            Binding::Key(key) => keyboard.press(key),
+            Binding::Mouse(button) => mouse.press(button),
+            Binding::Pad(_) => (),
+        }
+    }
+
+    pub fn release(self, keyboard: &mut Input<KeyCode>, mouse: &mut Input<MouseButton>) {
+        match self {
+            Binding::Key(key) => keyboard.release(key),
+            Binding::Mouse(button) => mouse.release(button),
+            Binding::Pad(_) => (),
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("Mouse {:?}", button),
+            Binding::Pad(button) => format!("Pad {:?}", button),
+        }
+    }
+}
+
+/// Inputs the player has rebound. Anything missing uses its default, so only changes are saved.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Bindings {
+    overrides: BTreeMap<Action, Binding>,
+    // `None` unbinds an action that has a default gamepad button
+    #[serde(default)]
+    pad_overrides: BTreeMap<Action, Option<Binding>>,
+}
+
+impl Bindings {
+    /// The keyboard or mouse binding, every action has one
+    pub fn get(&self, action: Action) -> Binding {
+        self.overrides.get(&action).copied().unwrap_or_else(|| action.default_binding())
+    }
+
+    pub fn get_pad(&self, action: Action) -> Option<Binding> {
+        self.pad_overrides.get(&action).copied().unwrap_or_else(|| action.default_pad_binding())
+    }
+
+    fn slot(&self, action: Action, pad: bool) -> Option<Binding> {
+        match pad {
+            true => self.get_pad(action),
+            false => Some(self.get(action)),
+        }
+    }
+
+    fn set_slot(&mut self, action: Action, pad: bool, binding: Option<Binding>) {
+        match (pad, binding) {
+            (true, binding) if binding == action.default_pad_binding() => { self.pad_overrides.remove(&action); },
+            (true, binding) => { self.pad_overrides.insert(action, binding); },
+            (false, Some(binding)) if binding == action.default_binding() => { self.overrides.remove(&action); },
+            (false, Some(binding)) => { self.overrides.insert(action, binding); },
+            (false, None) => (),
+        }
+    }
+
+    /// Replaces the binding in the slot matching the kind of input
+    pub fn set(&mut self, action: Action, binding: Binding) {
+        self.set_slot(action, binding.is_pad(), Some(binding));
+    }
+
+    /// Another action in the same context already on `binding`
+    pub fn conflict(&self, action: Action, binding: Binding) -> Option<Action> {
+        Action::all().find(|other| *other != action
+            && other.is_editor() == action.is_editor()
+            && self.slot(*other, binding.is_pad()) == Some(binding))
+    }
+
+    /// Binds `action`, swapping bindings with whatever action already used the input.
+    /// Returns the action that was moved.
+    pub fn rebind(&mut self, action: Action, binding: Binding) -> Option<Action> {
+        let pad = binding.is_pad();
+        let conflict = self.conflict(action, binding);
+        if let Some(other) = conflict {
+            self.set_slot(other, pad, self.slot(action, pad));
+        }
+        self.set(action, binding);
+        conflict
+    }
+
+    /// Actions sharing their input with another, only possible through an edited save
+    pub fn conflicts(&self) -> Vec<Action> {
+        Action::all().filter(|action| [false, true].into_iter().any(|pad| {
+            self.slot(*action, pad).is_some_and(|binding| self.conflict(*action, binding).is_some())
+        })).collect()
+    }
+}
+
+/// How far a stick has to move before it counts, as a fraction of its range
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct Deadzones {
+    // Moves and reels
+    pub left_stick: f32,
+    // Aims
+    pub right_stick: f32,
+}
+
+impl Default for Deadzones {
+    fn default() -> Self {
+        Self { left_stick: 0.2, right_stick: 0.25 }
+    }
+}
+
+/// Stick readings with deadzones applied
+#[derive(Default)]
+pub struct AnalogInput {
+    // Direction of the right stick while it's held past the deadzone
+    pub aim: Option<Vec2>,
+    // Left stick up reels in, down reels out, from -1 to 1
+    pub reel: f32,
+    // Left stick sideways, from -1 to 1
+    pub movement: f32,
+}
+
+/// Scales `value` so it starts from zero at the edge of the deadzone
+pub fn apply_deadzone(value: f32, deadzone: f32) -> f32 {
+    match value.abs() > deadzone {
+        true => value.signum() * (value.abs() - deadzone) / (1.0 - deadzone),
+        false => 0.0,
+    }
+}
+
+/// Every device the bindings can refer to
+#[derive(SystemParam)]
+pub struct RawInput<'w, 's> {
+    pub keyboard: Res<'w, Input<KeyCode>>,
+    pub mouse: Res<'w, Input<MouseButton>>,
+    pub gamepads: Res<'w, Gamepads>,
+    pub pad_buttons: Res<'w, Input<GamepadButton>>,
+    pub pad_axes: Res<'w, Axis<GamepadAxis>>,
+
+    #[system_param(ignore)]
+    marker: PhantomData<&'s usize>,
+}
+
+impl<'w, 's> RawInput<'w, 's> {
+    pub fn pressed(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.keyboard.pressed(key),
+            Binding::Mouse(button) => self.mouse.pressed(button),
+            Binding::Pad(button) => self.gamepads.iter()
+                .any(|pad| self.pad_buttons.pressed(GamepadButton::new(*pad, button))),
+        }
+    }
+
+    /// Any input pressed this frame, used to pick a new binding
+    pub fn just_pressed(&self) -> Option<Binding> {
+        self.keyboard.get_just_pressed().next().map(|key| Binding::Key(*key))
+            .or_else(|| self.mouse.get_just_pressed().next().map(|button| Binding::Mouse(*button)))
+            .or_else(|| self.pad_buttons.get_just_pressed().next().map(|button| Binding::Pad(button.button_type)))
+    }
+
+    /// The stick pushed furthest across all connected gamepads
+    pub fn stick(&self, x: GamepadAxisType, y: GamepadAxisType) -> Vec2 {
+        self.gamepads.iter()
+            .map(|pad| Vec2::new(
+                self.pad_axes.get(GamepadAxis::new(*pad, x)).unwrap_or(0.0),
+                self.pad_axes.get(GamepadAxis::new(*pad, y)).unwrap_or(0.0),
+            ))
+            .max_by(|a, b| a.length_squared().total_cmp(&b.length_squared()))
+            .unwrap_or(Vec2::ZERO)
+    }
+}
+
+#[derive(SystemLabel)]
+pub struct UpdateActions;
+
+pub struct ActionPlugin;
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Bindings::default())
+            .insert_resource(Deadzones::default())
+            .insert_resource(AnalogInput::default())
+            .insert_resource(Input::<Action>::default())
+            .add_system_to_stage(CoreStage::PreUpdate, update_actions.label(UpdateActions).after(InputSystem));
+    }
+}
+
+pub fn update_actions(
+    raw: RawInput,
+    bindings: Res<Bindings>,
+    deadzones: Res<Deadzones>,
+    mut actions: ResMut<Input<Action>>,
+    mut analog: ResMut<AnalogInput>,
+    (state, hit_stop): (Res<State<AppState>>, Res<HitStop>),
+    mut was_frozen: Local<bool>,
+) {
+    let left = raw.stick(GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY);
+    let right = raw.stick(GamepadAxisType::RightStickX, GamepadAxisType::RightStickY);
+
+    analog.movement = apply_deadzone(left.x, deadzones.left_stick);
+    analog.reel = apply_deadzone(left.y, deadzones.left_stick);
+    analog.aim = (right.length() > deadzones.right_stick).then(|| right.normalize());
+
+    // Gameplay skips hit-stop frames, so presses and releases are held until it ends.
+    // Each action changes at most once meanwhile, so recordings still see every press.
+    // Leaving gameplay, like pausing mid-stop, drops them.
+    let playing = *state.current() == AppState::Playing;
+    let frozen = playing && hit_stop.is_frozen();
+    if !frozen && !(*was_frozen && playing) {
+        actions.clear();
+    }
+    *was_frozen = frozen;
+
+    for action in Action::all() {
+        // Movement is digital, so the stick only picks a direction
+        let stick = match action {
+            Action::MoveLeft => analog.movement < 0.0,
+            Action::MoveRight => analog.movement > 0.0,
+            _ => false,
+        };
+
+        let pressed = stick
+            || raw.pressed(bindings.get(action))
+            || bindings.get_pad(action).is_some_and(|binding| raw.pressed(binding));
+
+        match pressed {
+            true if !(frozen && actions.just_released(action)) => actions.press(action),
+            false if !(frozen && actions.just_pressed(action)) => actions.release(action),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings_have_no_conflicts() {
+        assert_eq!(Bindings::default().conflicts(), Vec::new());
+    }
+
+    #[test]
+    fn rebinding_swaps_with_the_conflicting_action() {
+        let mut bindings = Bindings::default();
+
+        // Space is Jump's, and unused in the editor
+        let moved = bindings.rebind(Action::ReelIn, Binding::Key(KeyCode::Space));
+        assert_eq!(moved, Some(Action::Jump));
+        assert_eq!(bindings.get(Action::ReelIn), Binding::Key(KeyCode::Space));
+        assert_eq!(bindings.get(Action::Jump), Binding::Key(KeyCode::W));
+
+        // Jump now shares W with the editor's NudgeUp, which doesn't count
+        assert_eq!(bindings.conflicts(), Vec::new());
+
+        assert_eq!(bindings.rebind(Action::Jump, Binding::Key(KeyCode::Space)), Some(Action::ReelIn));
+        assert_eq!(bindings, Bindings::default());
+    }
+
+    #[test]
+    fn gamepad_buttons_rebind_in_their_own_slot() {
+        let mut bindings = Bindings::default();
+
+        // South is Jump's, and the keyboard side is left alone
+        let moved = bindings.rebind(Action::Restart, Binding::Pad(GamepadButtonType::South));
+        assert_eq!(moved, Some(Action::Jump));
+        assert_eq!(bindings.get_pad(Action::Jump), Some(Binding::Pad(GamepadButtonType::Select)));
+        assert_eq!(bindings.get(Action::Restart), Binding::Key(KeyCode::R));
+
+        // Actions without a button take the other's, leaving it unbound
+        bindings.rebind(Action::ToggleAimPreview, Binding::Pad(GamepadButtonType::South));
+        assert_eq!(bindings.get_pad(Action::Restart), None);
+        assert_eq!(bindings.conflicts(), Vec::new());
+    }
+}
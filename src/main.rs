@@ -1,35 +1,141 @@
-use bevy::{prelude::*, window::{WindowPlugin, WindowMode}, app::AppExit};
+use std::{io, path::PathBuf};
+
+use cli::Command;
+
+use bevy::{prelude::*, window::WindowPlugin};
 use bevy_inspector_egui::WorldInspectorPlugin;
 use bevy_rapier2d::prelude::*;
-use editor::{serde::SaveLoaded, editor::EditorPlugin};
-use game::{level::{level_startup, LevelEntity}, GamePlugin};
-use util::{Cursor, cursor_pos, preload_assets, PreloadedAssets};
+use display::{DisplayPlugin, DisplaySettings, world_camera};
+use editor::{serde::{SaveLoaded, SerdeLevel}, editor::EditorPlugin, migrate::migrate_levels};
+use game::{player::camera::FollowCamera, level::{level_startup, level_paths, LevelEntity, LevelHash, LevelList, CurrentLevel}, GamePlugin};
+use input::ActionPlugin;
+use menu::{AppState, MenuPlugin, pause_physics};
+use replay::{RecordPlugin, ReplayPlugin, Recording};
+use save::{SaveProfile, SavePlugin, FileStorage, SAVE_DIR};
+use util::{Cursor, MainCamera, cursor_pos, preload_assets, PreloadedAssets};
 
+pub mod cli;
 pub mod game;
 pub mod editor;
 pub mod util;
 pub mod constants;
+pub mod display;
+pub mod headless;
+pub mod input;
+pub mod menu;
+pub mod replay;
+pub mod save;
 
 pub const METERS_PER_PIXEL: f32 = 1.0 / 1000.0;
 
 fn main() {
-    App::new()
-        .insert_resource(WindowDescriptor {
-            mode: WindowMode::BorderlessFullscreen,
-            ..default()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (level, edit) = match Command::parse(&args) {
+        Ok(Command::Launch { level, edit }) => (level, edit),
+        // Tooling commands do their work and exit without opening a window
+        Ok(command) => std::process::exit(cli::run_tool(command)),
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        },
+    };
+
+    // Checked before anything opens so a bad path gets an error instead of a panic
+    if let Some(path) = &level {
+        match SerdeLevel::load(path) {
+            Ok(_) => (),
+            // Editing a level that doesn't exist yet starts a new one
+            Err(e) if edit && e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => {
+                eprintln!("error: couldn't load {}: {}\n\n{}", path.display(), e, cli::USAGE);
+                std::process::exit(2);
+            },
+        }
+    }
+
+    let replay = arg_value("--replay").map(PathBuf::from).map(|path| {
+        Recording::load(&path).unwrap_or_else(|e| {
+            eprintln!("error: couldn't load replay {}: {}", path.display(), e);
+            std::process::exit(1);
         })
-        .add_plugins(DefaultPlugins)
+    });
+
+    if std::env::args().any(|arg| arg == "--headless") {
+        headless::run_headless(headless::HEADLESS_FRAMES, replay);
+        return
+    }
+
+    // Loaded first so the window opens with the saved mode and size
+    let mut profile = SaveProfile::load(Box::new(FileStorage::new(SAVE_DIR)));
+
+    let mut app = App::new();
+    app.insert_resource(profile.data.settings.display.window_descriptor())
+        .add_plugins(DefaultPlugins);
         // .add_plugin(WorldInspectorPlugin::new())
+
+    // Levels saved before shapes stored an angle are rewritten, keeping their times.
+    // Done once logging is set up so failures show.
+    migrate_levels(&level_paths().into_iter().chain(level.clone().filter(|path| path.exists())).collect::<Vec<_>>(), &mut profile);
+    app.insert_resource(profile);
+
+    // Recordings and replays go straight into the level, frames spent in menus would throw them off.
+    // So does naming a level to play or edit.
+    let record_path = arg_value("--record").map(PathBuf::from);
+    let initial_state = match (edit, record_path.is_some() || replay.is_some() || level.is_some()) {
+        (true, _) => AppState::Editing,
+        (false, true) => AppState::Playing,
+        (false, false) => AppState::Title,
+    };
+
+    if let Some(path) = record_path {
+        app.add_plugin(RecordPlugin { path });
+    }
+
+    if let Some(recording) = replay {
+        app.add_plugin(ReplayPlugin { recording });
+    }
+
+    add_game(&mut app, initial_state);
+
+    if let Some(path) = level {
+        app.insert_resource(CurrentLevel(path));
+    }
+
+    if edit {
+        app.add_plugin(EditorPlugin);
+    }
+
+    // app.add_plugin(RapierDebugRenderPlugin::default());
+    app.add_plugin(MenuPlugin)
+        .add_plugin(DisplayPlugin)
+        .add_system_to_stage(CoreStage::PreUpdate, cursor_pos)
+        .run();
+}
+
+/// Everything the game needs on top of the engine plugins, shared by the windowed and headless apps
+pub fn add_game(app: &mut App, initial_state: AppState) -> &mut App {
+    let levels = LevelList::discover();
+    let current_level = levels.levels.first()
+        .map(|level| CurrentLevel(level.path.clone()))
+        .unwrap_or_default();
+
+    app.add_state(initial_state)
+        .add_system(pause_physics)
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(1.0 / METERS_PER_PIXEL))
-        // .add_plugin(RapierDebugRenderPlugin::default())
+        .add_plugin(ActionPlugin)
         .add_plugin(GamePlugin)
+        .add_plugin(SavePlugin)
         .add_startup_system(setup)
         .add_startup_system(level_startup)
         .add_startup_system(preload_assets)
         .insert_resource(PreloadedAssets::new())
         .insert_resource(SaveLoaded(false))
         .insert_resource(LevelEntity { entity: None })
+        .insert_resource(LevelHash::default())
+        .insert_resource(levels)
+        .insert_resource(current_level)
         .insert_resource(Cursor::default())
+        .insert_resource(DisplaySettings::default())
         .insert_resource(RapierConfiguration {
             gravity: Vec2::new(0.0, -750.0),
             timestep_mode: TimestepMode::Fixed { dt: 1.0 / 60.0, substeps: 16 },
@@ -38,22 +144,19 @@ fn main() {
         })
         .insert_resource(ClearColor(Color::BLACK))
         .insert_resource(Msaa { samples: 4 })
-        .add_system_to_stage(CoreStage::PreUpdate, cursor_pos)
-        .add_system(quit)
-        .run();
+}
+
+/// Value following a `--flag` on the command line
+pub fn arg_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != flag);
+    args.next()?;
+    args.next()
 }
 
 pub fn setup(
     mut commands: Commands,
 ) {
-    commands.spawn_bundle(Camera2dBundle::default());
+    commands.spawn_bundle(world_camera())
+        .insert(MainCamera)
+        .insert(FollowCamera::default());
 }
-
-pub fn quit(
-    mut exit: EventWriter<AppExit>,
-    keyboard: Res<Input<KeyCode>>,
-) {
-    if keyboard.pressed(KeyCode::Escape) {
-        exit.send(AppExit);
-    }
-}
\ No newline at end of file
@@ -0,0 +1,141 @@
+use bevy::prelude::*;
+
+use crate::input::{Action, Bindings, RawInput};
+
+use super::{MenuAction, RebuildMenu, widgets::{spawn_menu_root, spawn_heading, spawn_button, spawn_sized_button, spawn_text, WARNING_COLOR}};
+
+pub const ROW_SIZE: Vec2 = Vec2::new(760.0, 46.0);
+// The editor has too many actions for one column
+pub const EDITOR_ROW_SIZE: Vec2 = Vec2::new(500.0, 40.0);
+pub const EDITOR_ROWS_PER_COLUMN: usize = 10;
+
+/// The action waiting for a new input, if any
+#[derive(Default)]
+pub struct Rebinding {
+    pub action: Option<Action>,
+    // Only set once every mouse button is up, so the click that started rebinding isn't taken
+    armed: bool,
+    // Shown under the heading after an action had to be moved to make room
+    pub message: Option<String>,
+    // Whether the editor's actions are listed instead of the game's
+    pub editor: bool,
+}
+
+impl Rebinding {
+    pub fn start(&mut self, action: Action) {
+        *self = Self { action: Some(action), armed: false, message: None, editor: self.editor };
+    }
+}
+
+pub fn spawn_controls(commands: &mut Commands, font: Handle<Font>, bindings: &Bindings, rebinding: &Rebinding) {
+    let conflicts = bindings.conflicts();
+
+    spawn_menu_root(commands).with_children(|menu| {
+        spawn_heading(menu, &font, "Controls");
+
+        if let Some(message) = &rebinding.message {
+            spawn_text(menu, &font, message, 28.0, Color::WHITE);
+        }
+
+        let section = match rebinding.editor {
+            true => "Showing editor controls",
+            false => "Showing game controls",
+        };
+        spawn_button(menu, &font, section, MenuAction::SwitchControls);
+
+        // Game and editor actions only conflict within their own section
+        match rebinding.editor {
+            true => {
+                menu.spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::FlexStart,
+                        ..default()
+                    },
+                    color: UiColor(Color::NONE),
+                    ..default()
+                }).with_children(|row| {
+                    for actions in Action::EDITOR.chunks(EDITOR_ROWS_PER_COLUMN) {
+                        row.spawn_bundle(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::ColumnReverse,
+                                ..default()
+                            },
+                            color: UiColor(Color::NONE),
+                            ..default()
+                        }).with_children(|column| {
+                            for action in actions.iter().copied() {
+                                let (label, color) = action_row(action, bindings, rebinding, &conflicts);
+                                spawn_sized_button(column, &font, &label, MenuAction::Rebind(action), EDITOR_ROW_SIZE, 24.0, color);
+                            }
+                        });
+                    }
+                });
+            },
+            false => for action in Action::GAME {
+                let (label, color) = action_row(action, bindings, rebinding, &conflicts);
+                spawn_sized_button(menu, &font, &label, MenuAction::Rebind(action), ROW_SIZE, 28.0, color);
+            },
+        }
+
+        spawn_button(menu, &font, "Reset to defaults", MenuAction::ResetBindings);
+        spawn_button(menu, &font, "Back", MenuAction::Back);
+    });
+}
+
+/// Label of an action's row, in the warning color while it shares its input
+fn action_row(action: Action, bindings: &Bindings, rebinding: &Rebinding, conflicts: &[Action]) -> (String, Color) {
+    let binding = match rebinding.action == Some(action) {
+        true => "press a key or button, Escape to cancel".to_string(),
+        false => format!(
+            "{} / {}",
+            bindings.get(action).name(),
+            bindings.get_pad(action).map_or("-".to_string(), |binding| binding.name()),
+        ),
+    };
+    let color = match conflicts.contains(&action) {
+        true => WARNING_COLOR,
+        false => Color::WHITE,
+    };
+
+    (format!("{}: {}", action.name(), binding), color)
+}
+
+pub fn capture_binding(
+    raw: RawInput,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<Bindings>,
+    mut rebuild: ResMut<RebuildMenu>,
+) {
+    let action = match rebinding.action {
+        Some(action) => action,
+        None => return,
+    };
+
+    if !rebinding.armed {
+        rebinding.armed = raw.mouse.get_pressed().next().is_none();
+        return
+    }
+
+    if raw.keyboard.just_pressed(KeyCode::Escape) {
+        rebinding.action = None;
+        **rebuild = true;
+        return
+    }
+
+    let binding = match raw.just_pressed() {
+        Some(binding) => binding,
+        None => return,
+    };
+
+    rebinding.message = bindings.rebind(action, binding)
+        .map(|moved| {
+            let now = match binding.is_pad() {
+                true => bindings.get_pad(moved).map_or("nothing".to_string(), |binding| binding.name()),
+                false => bindings.get(moved).name(),
+            };
+            format!("{} moved to {}", moved.name(), now)
+        });
+    rebinding.action = None;
+    **rebuild = true;
+}
@@ -0,0 +1,69 @@
+use bevy::prelude::*;
+
+use crate::{util::physics_dt, game::timer::format_time};
+
+use super::{MenuAction, MenuValues, widgets::{spawn_menu_root, spawn_heading, spawn_button, spawn_text, BUTTON_COLOR, DISABLED_COLOR}};
+
+pub const CELL_SIZE: Vec2 = Vec2::new(260.0, 140.0);
+
+pub fn spawn_level_select(commands: &mut Commands, font: Handle<Font>, values: &MenuValues) {
+    let dt = physics_dt(&values.rapier_config);
+
+    spawn_menu_root(commands).with_children(|menu| {
+        spawn_heading(menu, &font, "Levels");
+
+        // UI is laid out bottom to top, so rows wrap in reverse to fill downwards
+        menu.spawn_bundle(NodeBundle {
+            style: Style {
+                max_size: Size::new(Val::Percent(80.0), Val::Undefined),
+                flex_wrap: FlexWrap::WrapReverse,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            color: UiColor(Color::NONE),
+            ..default()
+        }).with_children(|grid| {
+            for (index, level) in values.levels.levels.iter().enumerate() {
+                let unlocked = values.levels.is_unlocked(index, &values.profile);
+                let best = values.profile.data.times.level(level.hash).and_then(|times| times.pb_split(None));
+
+                let mut status = match (unlocked, best) {
+                    (false, _) => "Locked".to_string(),
+                    (true, Some(frames)) => format!("Best {}", format_time(frames as f32 * dt)),
+                    (true, None) => "Not finished".to_string(),
+                };
+                if unlocked && level.collectibles > 0 {
+                    let found = values.profile.data.found_collectibles(level.hash).map_or(0, |found| found.len());
+                    status += &format!("  {}/{}", found, level.collectibles);
+                }
+
+                let mut cell = grid.spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(CELL_SIZE.x), Val::Px(CELL_SIZE.y)),
+                        margin: UiRect::all(Val::Px(8.0)),
+                        flex_direction: FlexDirection::ColumnReverse,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    color: UiColor(BUTTON_COLOR),
+                    ..default()
+                });
+
+                // Locked levels keep the cell but can't be clicked
+                match unlocked {
+                    true => cell.insert(MenuAction::StartLevel(index)),
+                    false => cell.remove::<Interaction>(),
+                };
+
+                let text_color = match unlocked { true => Color::WHITE, false => DISABLED_COLOR };
+                cell.with_children(|cell| {
+                    spawn_text(cell, &font, &level.name, 40.0, text_color);
+                    spawn_text(cell, &font, &status, 28.0, text_color);
+                });
+            }
+        });
+
+        spawn_button(menu, &font, "Back", MenuAction::ToTitle);
+    });
+}
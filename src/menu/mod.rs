@@ -0,0 +1,277 @@
+use std::marker::PhantomData;
+
+use bevy::{prelude::*, app::AppExit, ecs::{system::SystemParam, schedule::ShouldRun}};
+use bevy_rapier2d::prelude::RapierConfiguration;
+
+use crate::{save::SaveProfile, input::{Action, Bindings, Deadzones}, display::DisplaySettings, game::feedback::{FeedbackSettings, HitStop}};
+use crate::game::{player::{spawn::Respawn, components::RopeMode, controls::aim::AimSettings}, level::{LevelList, LoadLevel}};
+
+use self::{screens::{spawn_title, spawn_pause, spawn_settings}, level_select::spawn_level_select, controls::{Rebinding, spawn_controls, capture_binding}};
+
+pub mod controls;
+pub mod level_select;
+pub mod screens;
+pub mod widgets;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AppState {
+    Title,
+    LevelSelect,
+    Playing,
+    // Pushed on top of Playing
+    Paused,
+    // Pushed on top of Title or Paused, so Back returns to either
+    Settings,
+    // Pushed on top of Settings
+    Controls,
+    // Started with `spiderman edit`, the game is frozen while shapes are moved
+    Editing,
+}
+
+/// What a menu button does when clicked
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub enum MenuAction {
+    Play,
+    OpenSettings,
+    Exit,
+    Back,
+    Resume,
+    Restart,
+    ToTitle,
+    StartLevel(usize),
+    ToggleAimPreview,
+    ToggleAimAssist,
+    ToggleRopeMode,
+    CycleLeftDeadzone,
+    CycleRightDeadzone,
+    CycleDisplayMode,
+    CycleResolution,
+    ToggleScreenShake,
+    ToggleHitStop,
+    OpenControls,
+    SwitchControls,
+    Rebind(Action),
+    ResetBindings,
+}
+
+/// Root node of the menu on screen, despawned whenever the state changes
+#[derive(Component)]
+pub struct MenuRoot;
+
+/// Set to redraw the current menu, for when the values it shows change
+#[derive(Deref, DerefMut, Default)]
+pub struct RebuildMenu(pub bool);
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RebuildMenu::default())
+            .insert_resource(Rebinding::default())
+            .add_system(show_menu)
+            .add_system(menu_actions)
+            .add_system(escape_menu)
+            .add_system(capture_binding.after(escape_menu).after(menu_actions))
+            .add_system(widgets::button_colors);
+    }
+}
+
+/// Run criteria for everything that moves the game forward. False in menus and
+/// during hit-stop, and usable in any stage unlike the state's own criteria.
+pub fn playing(state: Res<State<AppState>>, hit_stop: Res<HitStop>) -> ShouldRun {
+    match *state.current() == AppState::Playing && !hit_stop.is_frozen() {
+        true => ShouldRun::Yes,
+        false => ShouldRun::No,
+    }
+}
+
+/// Physics only steps while playing, so pausing freezes the body in place
+pub fn pause_physics(
+    state: Res<State<AppState>>,
+    hit_stop: Res<HitStop>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    let active = *state.current() == AppState::Playing && !hit_stop.is_frozen();
+    if rapier_config.physics_pipeline_active != active {
+        rapier_config.physics_pipeline_active = active;
+    }
+}
+
+pub fn show_menu(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    mut rebuild: ResMut<RebuildMenu>,
+    mut shown: Local<Option<AppState>>,
+    menus: Query<Entity, With<MenuRoot>>,
+
+    asset_server: Res<AssetServer>,
+    menu_values: MenuValues,
+) {
+    // The state resource is also marked changed when a transition is only queued
+    let current = *state.current();
+    if *shown == Some(current) && !**rebuild { return }
+    *shown = Some(current);
+    **rebuild = false;
+
+    for menu in menus.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    match current {
+        AppState::Title => spawn_title(&mut commands, font),
+        AppState::LevelSelect => spawn_level_select(&mut commands, font, &menu_values),
+        AppState::Playing | AppState::Editing => (),
+        AppState::Paused => spawn_pause(&mut commands, font),
+        AppState::Settings => spawn_settings(&mut commands, font, &menu_values),
+        AppState::Controls => spawn_controls(&mut commands, font, &menu_values.bindings, &menu_values.rebinding),
+    }
+}
+
+/// Everything the menus display or change
+#[derive(SystemParam)]
+pub struct MenuValues<'w, 's> {
+    pub levels: Res<'w, LevelList>,
+    pub profile: Res<'w, SaveProfile>,
+    pub rapier_config: Res<'w, RapierConfiguration>,
+    pub aim_settings: ResMut<'w, AimSettings>,
+    pub rope_mode: ResMut<'w, RopeMode>,
+    pub bindings: ResMut<'w, Bindings>,
+    pub deadzones: ResMut<'w, Deadzones>,
+    pub display: ResMut<'w, DisplaySettings>,
+    pub feedback: ResMut<'w, FeedbackSettings>,
+    pub rebinding: ResMut<'w, Rebinding>,
+
+    #[system_param(ignore)]
+    marker: PhantomData<&'s usize>,
+}
+
+pub fn menu_actions(
+    mut state: ResMut<State<AppState>>,
+    mut rebuild: ResMut<RebuildMenu>,
+    mut respawn: ResMut<Respawn>,
+    mut menu_values: MenuValues,
+    mut load_level: EventWriter<LoadLevel>,
+    mut exit: EventWriter<AppExit>,
+
+    buttons: Query<(&Interaction, &MenuAction), Changed<Interaction>>,
+) {
+    // Clicks while rebinding are the new input, not button presses
+    if menu_values.rebinding.action.is_some() { return }
+
+    for (interaction, action) in buttons.iter() {
+        if *interaction != Interaction::Clicked { continue }
+
+        // Transitions fail when one is already queued this frame, the extra click is dropped
+        let _ = match *action {
+            MenuAction::Play => state.set(AppState::LevelSelect),
+            MenuAction::OpenSettings => state.push(AppState::Settings),
+            MenuAction::Exit => {
+                exit.send(AppExit);
+                Ok(())
+            },
+            MenuAction::Back => state.pop(),
+            MenuAction::Resume => state.pop(),
+            MenuAction::Restart => {
+                **respawn = true;
+                state.pop()
+            },
+            MenuAction::ToTitle => state.replace(AppState::Title),
+            MenuAction::StartLevel(index) => {
+                load_level.send(LoadLevel { path: menu_values.levels.levels[index].path.clone() });
+                state.set(AppState::Playing)
+            },
+            MenuAction::ToggleAimPreview => {
+                menu_values.aim_settings.preview = !menu_values.aim_settings.preview;
+                **rebuild = true;
+                Ok(())
+            },
+            MenuAction::ToggleAimAssist => {
+                menu_values.aim_settings.assist = !menu_values.aim_settings.assist;
+                **rebuild = true;
+                Ok(())
+            },
+            MenuAction::ToggleRopeMode => {
+                *menu_values.rope_mode = menu_values.rope_mode.toggled();
+                **rebuild = true;
+                Ok(())
+            },
+            MenuAction::CycleLeftDeadzone => {
+                menu_values.deadzones.left_stick = next_deadzone(menu_values.deadzones.left_stick);
+                **rebuild = true;
+                Ok(())
+            },
+            MenuAction::CycleRightDeadzone => {
+                menu_values.deadzones.right_stick = next_deadzone(menu_values.deadzones.right_stick);
+                **rebuild = true;
+                Ok(())
+            },
+            MenuAction::CycleDisplayMode => {
+                menu_values.display.mode = menu_values.display.mode.next();
+                **rebuild = true;
+                Ok(())
+            },
+            MenuAction::CycleResolution => {
+                menu_values.display.resolution = menu_values.display.next_resolution();
+                **rebuild = true;
+                Ok(())
+            },
+            MenuAction::ToggleScreenShake => {
+                menu_values.feedback.screen_shake = !menu_values.feedback.screen_shake;
+                **rebuild = true;
+                Ok(())
+            },
+            MenuAction::ToggleHitStop => {
+                menu_values.feedback.hit_stop = !menu_values.feedback.hit_stop;
+                **rebuild = true;
+                Ok(())
+            },
+            MenuAction::OpenControls => {
+                menu_values.rebinding.message = None;
+                state.push(AppState::Controls)
+            },
+            MenuAction::SwitchControls => {
+                menu_values.rebinding.editor = !menu_values.rebinding.editor;
+                menu_values.rebinding.message = None;
+                **rebuild = true;
+                Ok(())
+            },
+            MenuAction::Rebind(action) => {
+                menu_values.rebinding.start(action);
+                **rebuild = true;
+                Ok(())
+            },
+            MenuAction::ResetBindings => {
+                *menu_values.bindings = Bindings::default();
+                menu_values.rebinding.message = None;
+                **rebuild = true;
+                Ok(())
+            },
+        };
+    }
+}
+
+/// Steps through deadzones from 5% to 40%, wrapping around
+pub fn next_deadzone(deadzone: f32) -> f32 {
+    let step = (deadzone / 0.05).round() as u32 + 1;
+    match step > 8 {
+        true => 0.05,
+        false => step as f32 * 0.05,
+    }
+}
+
+/// The pause action pauses the game and backs out of whichever menu is open
+pub fn escape_menu(
+    actions: Res<Input<Action>>,
+    rebinding: Res<Rebinding>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if !actions.just_pressed(Action::Pause) || rebinding.action.is_some() { return }
+
+    let _ = match state.current() {
+        AppState::Title | AppState::Editing => Ok(()),
+        AppState::LevelSelect => state.set(AppState::Title),
+        AppState::Playing => state.push(AppState::Paused),
+        AppState::Paused | AppState::Settings | AppState::Controls => state.pop(),
+    };
+}
@@ -0,0 +1,58 @@
+use bevy::prelude::*;
+
+use crate::display::DisplayMode;
+
+use super::{MenuAction, MenuValues, widgets::{spawn_menu_root, spawn_heading, spawn_button, spawn_sized_button}};
+
+// Smaller than the other menus' buttons so every setting fits on one screen
+pub const SETTING_SIZE: Vec2 = Vec2::new(500.0, 46.0);
+
+pub fn spawn_title(commands: &mut Commands, font: Handle<Font>) {
+    spawn_menu_root(commands).with_children(|menu| {
+        spawn_heading(menu, &font, "Web Game");
+        spawn_button(menu, &font, "Play", MenuAction::Play);
+        spawn_button(menu, &font, "Settings", MenuAction::OpenSettings);
+        spawn_button(menu, &font, "Quit", MenuAction::Exit);
+    });
+}
+
+pub fn spawn_pause(commands: &mut Commands, font: Handle<Font>) {
+    spawn_menu_root(commands).with_children(|menu| {
+        spawn_heading(menu, &font, "Paused");
+        spawn_button(menu, &font, "Resume", MenuAction::Resume);
+        spawn_button(menu, &font, "Restart", MenuAction::Restart);
+        spawn_button(menu, &font, "Settings", MenuAction::OpenSettings);
+        spawn_button(menu, &font, "Quit", MenuAction::ToTitle);
+    });
+}
+
+pub fn spawn_settings(commands: &mut Commands, font: Handle<Font>, values: &MenuValues) {
+    let on_off = |on: bool| match on { true => "On", false => "Off" };
+
+    // Borderless always matches the monitor
+    let resolution = match values.display.mode {
+        DisplayMode::Borderless => "Native".to_string(),
+        _ => format!("{}x{}", values.display.resolution.0, values.display.resolution.1),
+    };
+
+    let rows = [
+        (format!("Aim preview: {}", on_off(values.aim_settings.preview)), MenuAction::ToggleAimPreview),
+        (format!("Aim assist: {}", on_off(values.aim_settings.assist)), MenuAction::ToggleAimAssist),
+        (format!("Rope: {:?}", *values.rope_mode), MenuAction::ToggleRopeMode),
+        (format!("Move deadzone: {:.0}%", values.deadzones.left_stick * 100.0), MenuAction::CycleLeftDeadzone),
+        (format!("Aim deadzone: {:.0}%", values.deadzones.right_stick * 100.0), MenuAction::CycleRightDeadzone),
+        (format!("Window: {:?}", values.display.mode), MenuAction::CycleDisplayMode),
+        (format!("Resolution: {}", resolution), MenuAction::CycleResolution),
+        (format!("Screen shake: {}", on_off(values.feedback.screen_shake)), MenuAction::ToggleScreenShake),
+        (format!("Hit-stop: {}", on_off(values.feedback.hit_stop)), MenuAction::ToggleHitStop),
+        ("Controls".to_string(), MenuAction::OpenControls),
+    ];
+
+    spawn_menu_root(commands).with_children(|menu| {
+        spawn_heading(menu, &font, "Settings");
+        for (label, action) in rows {
+            spawn_sized_button(menu, &font, &label, action, SETTING_SIZE, 28.0, Color::WHITE);
+        }
+        spawn_button(menu, &font, "Back", MenuAction::Back);
+    });
+}
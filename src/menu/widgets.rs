@@ -0,0 +1,94 @@
+use bevy::{prelude::*, ecs::system::EntityCommands};
+
+use super::{MenuRoot, MenuAction};
+
+pub const BACKGROUND_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.75);
+pub const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+pub const BUTTON_HOVERED_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+pub const BUTTON_PRESSED_COLOR: Color = Color::rgb(0.45, 0.45, 0.45);
+pub const DISABLED_COLOR: Color = Color::rgb(0.4, 0.4, 0.4);
+pub const WARNING_COLOR: Color = Color::rgb(0.9, 0.3, 0.3);
+
+/// Full screen column with everything centered, dimming the game behind it
+pub fn spawn_menu_root<'w, 's, 'a>(commands: &'a mut Commands<'w, 's>) -> EntityCommands<'w, 's, 'a> {
+    let mut root = commands.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::ColumnReverse,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        color: UiColor(BACKGROUND_COLOR),
+        ..default()
+    });
+    root.insert(MenuRoot);
+    root
+}
+
+pub fn spawn_text(parent: &mut ChildBuilder, font: &Handle<Font>, text: &str, font_size: f32, color: Color) {
+    parent.spawn_bundle(TextBundle::from_section(
+        text,
+        TextStyle {
+            font: font.clone(),
+            font_size,
+            color,
+        },
+    ).with_style(Style {
+        margin: UiRect::all(Val::Px(10.0)),
+        ..default()
+    }));
+}
+
+pub fn spawn_heading(parent: &mut ChildBuilder, font: &Handle<Font>, text: &str) {
+    spawn_text(parent, font, text, 100.0, Color::WHITE);
+}
+
+pub fn spawn_button(parent: &mut ChildBuilder, font: &Handle<Font>, label: &str, action: MenuAction) {
+    spawn_sized_button(parent, font, label, action, Vec2::new(400.0, 70.0), 40.0, Color::WHITE);
+}
+
+pub fn spawn_sized_button(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    label: &str,
+    action: MenuAction,
+    size: Vec2,
+    font_size: f32,
+    text_color: Color,
+) {
+    parent.spawn_bundle(ButtonBundle {
+        style: Style {
+            size: Size::new(Val::Px(size.x), Val::Px(size.y)),
+            margin: UiRect::all(Val::Px(8.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        color: UiColor(BUTTON_COLOR),
+        ..default()
+    }).insert(action)
+    .with_children(|button| {
+        button.spawn_bundle(TextBundle::from_section(
+            label,
+            TextStyle {
+                font: font.clone(),
+                font_size,
+                color: text_color,
+            },
+        ));
+    });
+}
+
+pub fn button_colors(
+    mut buttons: Query<(&Interaction, &mut UiColor), Changed<Interaction>>,
+) {
+    for (interaction, mut color) in buttons.iter_mut() {
+        *color = UiColor(match interaction {
+            Interaction::Clicked => BUTTON_PRESSED_COLOR,
+            Interaction::Hovered => BUTTON_HOVERED_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        });
+    }
+}
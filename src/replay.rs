@@ -0,0 +1,344 @@
+use std::{fs, io, path::PathBuf};
+
+use bevy::{prelude::*, math::Vec3Swizzles, app::AppExit, input::{InputSystem, mouse::MouseMotion}};
+
+use crate::{menu::playing, input::{Action, Bindings, UpdateActions, AnalogInput}, util::{Cursor, cursor_pos, ByteReader, push_vec2, invalid_data}, game::player::components::Player};
+
+pub const REPLAY_MAGIC: &[u8; 4] = b"SPRP";
+pub const REPLAY_VERSION: u8 = 3;
+
+// Body positions are stored this often to detect replays drifting from the recording
+pub const BODY_SAMPLE_INTERVAL: u32 = 30;
+pub const DIVERGENCE_TOLERANCE: f32 = 1.0;
+
+// Actions that affect gameplay, one bit each. Version 1 stored the left mouse button and
+// the keys for the first eight in this order, so those files read the same.
+pub const RECORDED_ACTIONS: [Action; 11] = [
+    Action::ShootWeb,
+    Action::Restart,
+    Action::ToggleRopeMode,
+    Action::ToggleAimPreview,
+    Action::ToggleAimAssist,
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::Jump,
+    Action::Release,
+    Action::ReelIn,
+    Action::ReelOut,
+];
+
+const HAS_REEL_BIT: u16 = 1 << 14;
+const HAS_MOTION_BIT: u16 = 1 << 15;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameInput {
+    pub actions: u16,
+    pub cursor_pos: Vec2,
+    pub cursor_world_pos: Vec2,
+    pub mouse_motion: Vec2,
+    // Analog reel from a gamepad stick
+    pub reel: f32,
+}
+
+impl FrameInput {
+    pub fn action_pressed(&self, index: usize) -> bool {
+        self.actions & (1 << index) != 0
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Recording {
+    pub frames: Vec<FrameInput>,
+    pub body_positions: Vec<(u32, Vec2)>,
+}
+
+impl Recording {
+    /// Layout: magic, version, frame count, frames, sample count, samples. All little endian.
+    /// Each frame is a flag word, two cursor positions, then the mouse motion and reel only when non-zero.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16 + self.frames.len() * 18 + self.body_positions.len() * 12);
+        bytes.extend_from_slice(REPLAY_MAGIC);
+        bytes.push(REPLAY_VERSION);
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+
+        for frame in self.frames.iter() {
+            let mut flags = frame.actions;
+            if frame.mouse_motion != Vec2::ZERO { flags |= HAS_MOTION_BIT; }
+            if frame.reel != 0.0 { flags |= HAS_REEL_BIT; }
+
+            bytes.extend_from_slice(&flags.to_le_bytes());
+            push_vec2(&mut bytes, frame.cursor_pos);
+            push_vec2(&mut bytes, frame.cursor_world_pos);
+            if flags & HAS_MOTION_BIT != 0 {
+                push_vec2(&mut bytes, frame.mouse_motion);
+            }
+            if flags & HAS_REEL_BIT != 0 {
+                bytes.extend_from_slice(&frame.reel.to_le_bytes());
+            }
+        }
+
+        bytes.extend_from_slice(&(self.body_positions.len() as u32).to_le_bytes());
+        for (frame, pos) in self.body_positions.iter() {
+            bytes.extend_from_slice(&frame.to_le_bytes());
+            push_vec2(&mut bytes, *pos);
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.take(4)? != REPLAY_MAGIC {
+            return Err(invalid_data("not a replay file"));
+        }
+
+        let version = reader.take(1)?[0];
+        if version == 0 || version > REPLAY_VERSION {
+            return Err(invalid_data(&format!("unsupported replay version {}", version)));
+        }
+
+        let num_frames = reader.u32()?;
+        // Grown as frames are read, a corrupt count would otherwise allocate before failing
+        let mut frames = Vec::new();
+        for _ in 0..num_frames {
+            let flags = reader.u16()?;
+            let cursor_pos = reader.vec2()?;
+            let cursor_world_pos = reader.vec2()?;
+            let mouse_motion = match flags & HAS_MOTION_BIT != 0 {
+                true => reader.vec2()?,
+                false => Vec2::ZERO,
+            };
+            let reel = match flags & HAS_REEL_BIT != 0 {
+                true => reader.f32()?,
+                false => 0.0,
+            };
+
+            frames.push(FrameInput {
+                actions: flags & !(HAS_MOTION_BIT | HAS_REEL_BIT),
+                reel,
+                cursor_pos,
+                cursor_world_pos,
+                mouse_motion,
+            });
+        }
+
+        let num_samples = reader.u32()?;
+        let mut body_positions = Vec::new();
+        for _ in 0..num_samples {
+            body_positions.push((reader.u32()?, reader.vec2()?));
+        }
+
+        Ok(Self { frames, body_positions })
+    }
+
+    pub fn load(path: &PathBuf) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+pub struct InputRecorder {
+    pub path: PathBuf,
+    pub recording: Recording,
+}
+
+pub struct ReplayDriver {
+    pub recording: Recording,
+    pub frame: u32,
+    // Input being played back this frame
+    pub current: Option<FrameInput>,
+    pub diverged_at: Option<u32>,
+}
+
+impl ReplayDriver {
+    pub fn new(recording: Recording) -> Self {
+        Self { recording, frame: 0, current: None, diverged_at: None }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame as usize >= self.recording.frames.len()
+    }
+}
+
+pub struct RecordPlugin {
+    pub path: PathBuf,
+}
+
+impl Plugin for RecordPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputRecorder { path: self.path.clone(), recording: Recording::default() })
+            // Recorded after gameplay so it captures exactly what gameplay saw, whatever fed the input
+            // Paused and hit-stop frames are left out, so replays line up whatever the feedback settings
+            .add_system_to_stage(CoreStage::PostUpdate, record_input.with_run_criteria(playing))
+            .add_system_to_stage(CoreStage::PostUpdate, record_body_position.with_run_criteria(playing).after(record_input))
+            .add_system_to_stage(CoreStage::Last, save_recording);
+    }
+}
+
+/// Loaded up front so a bad file is reported before any window opens
+pub struct ReplayPlugin {
+    pub recording: Recording,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ReplayDriver::new(self.recording.clone()))
+            // Presses whatever the recorded actions are bound to now, so rebinding doesn't break replays
+            .add_system_to_stage(CoreStage::PreUpdate, drive_replay.with_run_criteria(playing).after(InputSystem).after(cursor_pos).before(UpdateActions))
+            .add_system_to_stage(CoreStage::PreUpdate, drive_replay_analog.with_run_criteria(playing).after(UpdateActions))
+            .add_system_to_stage(CoreStage::PostUpdate, check_divergence.with_run_criteria(playing));
+    }
+}
+
+pub fn record_input(
+    mut recorder: ResMut<InputRecorder>,
+    actions: Res<Input<Action>>,
+    analog: Res<AnalogInput>,
+    cursor: Res<Cursor>,
+    mut mouse_motion: EventReader<MouseMotion>,
+) {
+    let mut pressed = 0;
+    for (i, action) in RECORDED_ACTIONS.iter().enumerate() {
+        if actions.pressed(*action) { pressed |= 1 << i; }
+    }
+
+    recorder.recording.frames.push(FrameInput {
+        actions: pressed,
+        cursor_pos: cursor.pos,
+        cursor_world_pos: cursor.world_pos,
+        mouse_motion: mouse_motion.iter().fold(Vec2::ZERO, |acc, e| acc + e.delta),
+        reel: analog.reel,
+    });
+}
+
+pub fn record_body_position(
+    mut recorder: ResMut<InputRecorder>,
+    player_q: Query<&Player>,
+    transform_q: Query<&Transform>,
+) {
+    let frame = recorder.recording.frames.len() as u32 - 1;
+    if !frame.is_multiple_of(BODY_SAMPLE_INTERVAL) { return }
+
+    if let Ok(player) = player_q.get_single() {
+        let pos = transform_q.get(player.body).unwrap().translation.xy();
+        recorder.recording.body_positions.push((frame, pos));
+    }
+}
+
+pub fn save_recording(
+    recorder: Res<InputRecorder>,
+    mut exit: EventReader<AppExit>,
+) {
+    if exit.iter().count() == 0 { return }
+
+    fs::write(&recorder.path, recorder.recording.to_bytes()).expect("Couldn't write recording");
+    info!("Saved {} frames to {}", recorder.recording.frames.len(), recorder.path.display());
+}
+
+pub fn drive_replay(
+    mut driver: ResMut<ReplayDriver>,
+    bindings: Res<Bindings>,
+    mut mouse: ResMut<Input<MouseButton>>,
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut cursor: ResMut<Cursor>,
+    mut mouse_motion: ResMut<Events<MouseMotion>>,
+) {
+    // Live mouse movement would reel the web differently from the recording
+    mouse_motion.clear();
+
+    driver.current = driver.recording.frames.get(driver.frame as usize).copied();
+    let input = match driver.current {
+        Some(input) => input,
+        None => return,
+    };
+    driver.frame += 1;
+
+    for (i, action) in RECORDED_ACTIONS.iter().enumerate() {
+        let binding = bindings.get(*action);
+        match input.action_pressed(i) {
+            true => binding.press(&mut keyboard, &mut mouse),
+            false => binding.release(&mut keyboard, &mut mouse),
+        }
+    }
+
+    cursor.pos = input.cursor_pos;
+    cursor.world_pos = input.cursor_world_pos;
+
+    if input.mouse_motion != Vec2::ZERO {
+        mouse_motion.send(MouseMotion { delta: input.mouse_motion });
+    }
+}
+
+/// Sticks can't be pressed like buttons, so their readings are overwritten once actions are updated
+pub fn drive_replay_analog(
+    driver: Res<ReplayDriver>,
+    mut analog: ResMut<AnalogInput>,
+) {
+    let input = match driver.current {
+        Some(input) => input,
+        None => return,
+    };
+
+    analog.reel = input.reel;
+    // Aim is in the recorded cursor position already
+    analog.aim = None;
+}
+
+pub fn check_divergence(
+    mut driver: ResMut<ReplayDriver>,
+    player_q: Query<&Player>,
+    transform_q: Query<&Transform>,
+) {
+    if driver.diverged_at.is_some() || driver.frame == 0 { return }
+    let frame = driver.frame - 1;
+
+    let expected = match driver.recording.body_positions.iter().find(|(f, _)| *f == frame) {
+        Some((_, pos)) => *pos,
+        None => return,
+    };
+
+    if let Ok(player) = player_q.get_single() {
+        let pos = transform_q.get(player.body).unwrap().translation.xy();
+        let distance = pos.distance(expected);
+
+        if distance > DIVERGENCE_TOLERANCE {
+            warn!("Replay diverged at frame {}: body is {} px from the recording", frame, distance);
+            driver.diverged_at = Some(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::headless::{headless_app, run_frames, body_position, InputScript, ScriptAction};
+
+    #[test]
+    fn replay_reproduces_recorded_run() {
+        let path = std::env::temp_dir().join("spiderman_replay_test.sprp");
+
+        let mut script = InputScript::new().fire_web(30, Vec2::new(365.0, 254.0), 150);
+        for frame in 40..120 {
+            script = script.at(frame, ScriptAction::MouseMotion(Vec2::new(-30.0, 30.0)));
+        }
+
+        let mut app = headless_app(script);
+        app.add_plugin(RecordPlugin { path: path.clone() });
+        run_frames(&mut app, 240);
+        let recorded_end = body_position(&mut app).unwrap();
+
+        let bytes = app.world.resource::<InputRecorder>().recording.to_bytes();
+        assert_eq!(Recording::from_bytes(&bytes).unwrap(), app.world.resource::<InputRecorder>().recording);
+        fs::write(&path, bytes).unwrap();
+
+        let mut app = headless_app(InputScript::new());
+        app.add_plugin(ReplayPlugin { recording: Recording::load(&path).unwrap() });
+        run_frames(&mut app, 240);
+
+        assert_eq!(app.world.resource::<ReplayDriver>().diverged_at, None);
+        assert_eq!(body_position(&mut app).unwrap(), recorded_end);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,393 @@
+use std::{fs, io, path::PathBuf, collections::{BTreeMap, BTreeSet, HashMap}};
+
+use bevy::{prelude::*, app::AppExit};
+use bevy_rapier2d::prelude::RapierConfiguration;
+use serde::{Serialize, Deserialize};
+
+use crate::{util::{physics_dt, invalid_data}, display::DisplaySettings, game::feedback::FeedbackSettings, input::{Bindings, Deadzones}, menu::AppState, game::{run::RunFinished, timer::SpeedrunTimes, player::{components::*, controls::aim::AimSettings}}};
+
+pub const SAVE_VERSION: u32 = 1;
+pub const PROFILE_KEY: &str = "profile";
+pub const SAVE_DIR: &str = "./saves";
+
+/// Somewhere to keep whole save files by name. Writes must replace the old
+/// contents completely or not at all.
+pub trait SaveStorage: Send + Sync {
+    fn read(&self, key: &str) -> io::Result<Option<String>>;
+    fn write(&mut self, key: &str, contents: &str) -> io::Result<()>;
+}
+
+/// One `<key>.json` file per save in a directory, used on desktop
+pub struct FileStorage {
+    pub dir: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+impl SaveStorage for FileStorage {
+    fn read(&self, key: &str) -> io::Result<Option<String>> {
+        match fs::read_to_string(self.path(key)) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Written to a temporary file first, then renamed over the old one, so a
+    // crash halfway through never leaves a truncated save behind
+    fn write(&mut self, key: &str, contents: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let path = self.path(key);
+        let tmp_path = path.with_extension("json.tmp");
+        {
+            let mut file = fs::File::create(&tmp_path)?;
+            io::Write::write_all(&mut file, contents.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(tmp_path, path)
+    }
+}
+
+/// A flat string key-value store, like the browser's local storage on the web build
+pub trait KeyValueStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<String>;
+    fn set(&mut self, key: &str, value: &str) -> io::Result<()>;
+}
+
+/// Keeps saves in a key-value store under a common prefix. Setting a single
+/// key is atomic, so nothing extra is needed for safe writes.
+pub struct KeyValueStorage<S: KeyValueStore> {
+    pub store: S,
+    pub prefix: String,
+}
+
+impl<S: KeyValueStore> SaveStorage for KeyValueStorage<S> {
+    fn read(&self, key: &str) -> io::Result<Option<String>> {
+        Ok(self.store.get(&format!("{}{}", self.prefix, key)))
+    }
+
+    fn write(&mut self, key: &str, contents: &str) -> io::Result<()> {
+        self.store.set(&format!("{}{}", self.prefix, key), contents)
+    }
+}
+
+/// Nothing touches the disk, used by headless runs and tests
+#[derive(Default)]
+pub struct MemoryStorage {
+    pub saves: HashMap<String, String>,
+}
+
+impl SaveStorage for MemoryStorage {
+    fn read(&self, key: &str) -> io::Result<Option<String>> {
+        Ok(self.saves.get(key).cloned())
+    }
+
+    fn write(&mut self, key: &str, contents: &str) -> io::Result<()> {
+        self.saves.insert(key.to_string(), contents.to_string());
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub aim_preview: bool,
+    pub aim_assist: bool,
+    pub rope_mode: RopeMode,
+    pub bindings: Bindings,
+    pub deadzones: Deadzones,
+    pub display: DisplaySettings,
+    pub feedback: FeedbackSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            aim_preview: true,
+            aim_assist: false,
+            rope_mode: RopeMode::Chain,
+            bindings: Bindings::default(),
+            deadzones: Deadzones::default(),
+            display: DisplaySettings::default(),
+            feedback: FeedbackSettings::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Stats {
+    pub webs_shot: u32,
+    pub deaths: u32,
+    pub runs_finished: u32,
+    // Seconds of game time
+    pub play_time: f32,
+}
+
+/// Everything kept between sessions. Fields missing from older saves take their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SaveData {
+    pub version: u32,
+    pub unlocked_levels: BTreeSet<String>,
+    pub times: SpeedrunTimes,
+    // Collectible ids found, per level hash
+    pub collectibles: BTreeMap<String, BTreeSet<u32>>,
+    pub settings: Settings,
+    pub stats: Stats,
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        Self {
+            version: SAVE_VERSION,
+            unlocked_levels: BTreeSet::new(),
+            times: SpeedrunTimes::default(),
+            collectibles: BTreeMap::new(),
+            settings: Settings::default(),
+            stats: Stats::default(),
+        }
+    }
+}
+
+impl SaveData {
+    /// Brings a save written by any older version up to `SAVE_VERSION`
+    pub fn from_json(contents: &str) -> io::Result<Self> {
+        let mut value: serde_json::Value = serde_json::from_str(contents)
+            .map_err(|e| invalid_data(&e.to_string()))?;
+
+        let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+        if version > SAVE_VERSION {
+            return Err(invalid_data(&format!("save version {} is newer than this build ({})", version, SAVE_VERSION)));
+        }
+
+        // Version 0 saves had no version field and no other differences
+        value.as_object_mut()
+            .ok_or_else(|| invalid_data("save is not an object"))?
+            .insert("version".into(), SAVE_VERSION.into());
+
+        serde_json::from_value(value).map_err(|e| invalid_data(&e.to_string()))
+    }
+
+    pub fn found_collectibles(&self, level_hash: u64) -> Option<&BTreeSet<u32>> {
+        self.collectibles.get(&format!("{:016x}", level_hash))
+    }
+
+    /// Records a collectible as found, returning whether it wasn't already
+    pub fn find_collectible(&mut self, level_hash: u64, id: u32) -> bool {
+        self.collectibles.entry(format!("{:016x}", level_hash)).or_default().insert(id)
+    }
+
+    /// Moves everything kept for a level to a new hash, for when its file changed but the level didn't
+    pub fn rekey_level(&mut self, old_hash: u64, new_hash: u64) {
+        let (old, new) = (format!("{:016x}", old_hash), format!("{:016x}", new_hash));
+        if let Some(times) = self.times.levels.remove(&old) {
+            self.times.levels.entry(new.clone()).or_insert(times);
+        }
+        if let Some(found) = self.collectibles.remove(&old) {
+            self.collectibles.entry(new).or_default().extend(found);
+        }
+    }
+}
+
+pub struct SaveProfile {
+    storage: Box<dyn SaveStorage>,
+    pub data: SaveData,
+    dirty: bool,
+    // Set when the stored save couldn't be understood, so it is never overwritten
+    read_only: bool,
+}
+
+impl SaveProfile {
+    pub fn load(storage: Box<dyn SaveStorage>) -> Self {
+        let mut profile = Self { storage, data: SaveData::default(), dirty: false, read_only: false };
+
+        match profile.storage.read(PROFILE_KEY) {
+            Ok(Some(contents)) => match SaveData::from_json(&contents) {
+                Ok(data) => profile.data = data,
+                Err(e) => {
+                    warn!("Couldn't load save data, progress won't be saved this session: {}", e);
+                    profile.read_only = true;
+                },
+            },
+            Ok(None) => (),
+            Err(e) => {
+                warn!("Couldn't read save data, progress won't be saved this session: {}", e);
+                profile.read_only = true;
+            },
+        }
+
+        profile
+    }
+
+    pub fn in_memory() -> Self {
+        Self::load(Box::new(MemoryStorage::default()))
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        self.dirty = false;
+        if self.read_only { return Ok(()) }
+
+        self.storage.write(PROFILE_KEY, &serde_json::to_string_pretty(&self.data).unwrap())
+    }
+}
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(apply_saved_settings)
+            .add_system(store_settings)
+            .add_system(track_stats)
+            .add_system_to_stage(CoreStage::Last, flush_save);
+    }
+}
+
+pub fn apply_saved_settings(
+    profile: Res<SaveProfile>,
+    mut aim_settings: ResMut<AimSettings>,
+    mut rope_mode: ResMut<RopeMode>,
+    mut bindings: ResMut<Bindings>,
+    mut deadzones: ResMut<Deadzones>,
+    mut display: ResMut<DisplaySettings>,
+    mut feedback: ResMut<FeedbackSettings>,
+) {
+    let settings = &profile.data.settings;
+    aim_settings.preview = settings.aim_preview;
+    aim_settings.assist = settings.aim_assist;
+    *rope_mode = settings.rope_mode;
+    *bindings = settings.bindings.clone();
+    *deadzones = settings.deadzones;
+    *display = settings.display;
+    *feedback = settings.feedback;
+}
+
+pub fn store_settings(
+    mut profile: ResMut<SaveProfile>,
+    aim_settings: Res<AimSettings>,
+    rope_mode: Res<RopeMode>,
+    bindings: Res<Bindings>,
+    deadzones: Res<Deadzones>,
+    display: Res<DisplaySettings>,
+    feedback: Res<FeedbackSettings>,
+) {
+    let changed = aim_settings.is_changed() || rope_mode.is_changed() || bindings.is_changed()
+        || deadzones.is_changed() || display.is_changed() || feedback.is_changed();
+    if !changed { return }
+
+    let settings = Settings {
+        aim_preview: aim_settings.preview,
+        aim_assist: aim_settings.assist,
+        rope_mode: *rope_mode,
+        bindings: bindings.clone(),
+        deadzones: *deadzones,
+        display: *display,
+        feedback: *feedback,
+    };
+
+    if settings != profile.data.settings {
+        profile.data.settings = settings;
+        profile.mark_dirty();
+    }
+}
+
+pub fn track_stats(
+    state: Res<State<AppState>>,
+    rapier_config: Res<RapierConfiguration>,
+    mut profile: ResMut<SaveProfile>,
+    mut state_events: EventReader<PlayerStateChanged>,
+    mut finished: EventReader<RunFinished>,
+) {
+    // Play time alone doesn't mark the save dirty, it goes out with the next real change
+    if *state.current() == AppState::Playing {
+        profile.data.stats.play_time += physics_dt(&rapier_config);
+    }
+
+    for event in state_events.iter() {
+        match event.to {
+            PlayerState::Shooting => profile.data.stats.webs_shot += 1,
+            PlayerState::Dead => profile.data.stats.deaths += 1,
+            _ => (),
+        }
+    }
+
+    for _ in finished.iter() {
+        profile.data.stats.runs_finished += 1;
+    }
+}
+
+pub fn flush_save(
+    mut profile: ResMut<SaveProfile>,
+    mut exit: EventReader<AppExit>,
+) {
+    let exiting = exit.iter().count() > 0;
+    if !profile.dirty && !exiting { return }
+
+    if let Err(e) = profile.save() {
+        warn!("Couldn't write save data: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{game::run::Split, input::{Action, Binding}};
+
+    fn profile_with(contents: &str) -> SaveProfile {
+        let mut storage = MemoryStorage::default();
+        storage.write(PROFILE_KEY, contents).unwrap();
+        SaveProfile::load(Box::new(storage))
+    }
+
+    #[test]
+    fn save_round_trips_through_storage() {
+        let mut profile = SaveProfile::in_memory();
+        profile.data.unlocked_levels.insert("level".to_string());
+        profile.data.times.level_mut(7).record(&[Split { checkpoint: None, frames: 300 }]);
+        profile.data.settings.rope_mode = RopeMode::Wrapping;
+        profile.data.settings.bindings.rebind(Action::Jump, Binding::Mouse(MouseButton::Middle));
+        profile.data.stats.webs_shot = 12;
+        profile.save().unwrap();
+
+        let contents = profile.storage.read(PROFILE_KEY).unwrap().unwrap();
+        assert_eq!(profile_with(&contents).data, profile.data);
+    }
+
+    #[test]
+    fn unversioned_save_is_migrated() {
+        let profile = profile_with(r#"{ "stats": { "deaths": 3 } }"#);
+
+        assert!(!profile.read_only);
+        assert_eq!(profile.data.version, SAVE_VERSION);
+        assert_eq!(profile.data.stats.deaths, 3);
+        assert_eq!(profile.data.settings, Settings::default());
+    }
+
+    #[test]
+    fn newer_or_broken_saves_are_never_overwritten() {
+        let newer = format!(r#"{{ "version": {} }}"#, SAVE_VERSION + 1);
+
+        for contents in [newer.as_str(), "{ not json", "[1, 2]", "null"] {
+            let mut profile = profile_with(contents);
+            assert!(profile.read_only);
+
+            profile.data.stats.deaths = 1;
+            profile.save().unwrap();
+            assert_eq!(profile.storage.read(PROFILE_KEY).unwrap().unwrap(), contents);
+        }
+    }
+}
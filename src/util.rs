@@ -1,11 +1,22 @@
-use std::f32::consts::PI;
+use std::{f32::consts::PI, io};
 
-use bevy::{prelude::*, math::Vec3Swizzles, utils::HashMap, sprite::Mesh2dHandle};
+use bevy::{prelude::*, math::Vec3Swizzles, utils::HashMap, sprite::Mesh2dHandle, render::camera::RenderTarget};
+use bevy_rapier2d::prelude::{RapierConfiguration, TimestepMode};
 
 pub const DEGREES: f32 = PI / 180.0;
 
 pub type EntityQuery<'w, 's, T> = Query<'w, 's, Entity, With<T>>;
 
+/// Time advanced by one physics step. Gameplay timers use this instead of
+/// the frame time so that runs replay identically.
+pub fn physics_dt(config: &RapierConfiguration) -> f32 {
+    match config.timestep_mode {
+        TimestepMode::Fixed { dt, .. } => dt,
+        TimestepMode::Variable { max_dt, time_scale, .. } => max_dt * time_scale,
+        TimestepMode::Interpolated { dt, time_scale, .. } => dt * time_scale,
+    }
+}
+
 pub struct Cursor {
     pub pos: Vec2,
     pub prev_pos: Vec2,
@@ -37,48 +48,94 @@ impl Cursor {
     }
 }
 
+/// The camera that follows the player and that the cursor is mapped through.
+/// Other cameras, like a minimap, leave it off.
+#[derive(Component)]
+pub struct MainCamera;
+
+/// Normalized device coordinates of a point in window coordinates, with (-1, -1)
+/// at the bottom left of the camera's viewport. None until the camera has rendered.
+pub fn screen_to_ndc(camera: &Camera, screen_pos: Vec2) -> Option<Vec2> {
+    let (min, max) = camera.logical_viewport_rect()?;
+    let target_height = camera.logical_target_size()?.y;
+
+    // Viewports are placed from the top left, window coordinates start at the bottom left
+    let bottom_left = Vec2::new(min.x, target_height - max.y);
+    Some((screen_pos - bottom_left) / (max - min) * 2.0 - Vec2::ONE)
+}
+
+/// World position seen at a point in normalized device coordinates. Goes through the
+/// full projection and camera transform, so zoom, rotation and scaling modes all apply.
+pub fn ndc_to_world(camera: &Camera, camera_transform: &GlobalTransform, ndc: Vec2) -> Vec2 {
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
+    ndc_to_world.project_point3(ndc.extend(1.0)).xy()
+}
+
+/// World position under a point in window coordinates, as seen by the given camera
+pub fn screen_to_world(camera: &Camera, camera_transform: &GlobalTransform, screen_pos: Vec2) -> Option<Vec2> {
+    screen_to_ndc(camera, screen_pos).map(|ndc| ndc_to_world(camera, camera_transform, ndc))
+}
+
+/// Whether a point in window coordinates is inside the camera's viewport, for
+/// picking which of several cameras the cursor is over
+pub fn in_viewport(camera: &Camera, screen_pos: Vec2) -> bool {
+    screen_to_ndc(camera, screen_pos).is_some_and(|ndc| ndc.abs().cmple(Vec2::ONE).all())
+}
+
+/// Tracks the cursor over the main camera's window. `pos` is in logical pixels
+/// from the center of the viewport.
 pub fn cursor_pos(
-    projection: Query<&OrthographicProjection>,
-    c_transform_query: Query<&GlobalTransform, With<Camera>>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
 
-    windows: Res<Windows>,
     mut cursor: ResMut<Cursor>,
     mut cursor_input: EventReader<CursorMoved>,
 ) {
     cursor.prev_pos = cursor.pos;
     cursor.prev_world_pos = cursor.world_pos;
-
-    if let Some(cursor_moved) = cursor_input.iter().last() {        
-        let window = windows.get_primary().unwrap();
-        let window_size = Vec2::new(window.width(), window.height());
-
-        let pos = cursor_moved.position - (window_size / 2.0);
-
-        cursor.pos = pos;
-
+    cursor.moved = false;
+
+    let (camera, camera_transform) = match camera_q.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let half_size = match camera.logical_viewport_size() {
+        Some(size) => size / 2.0,
+        None => return,
+    };
+
+    let window = match camera.target {
+        RenderTarget::Window(window) => Some(window),
+        RenderTarget::Image(_) => None,
+    };
+
+    let cursor_moved = cursor_input.iter().rfind(|event| Some(event.id) == window);
+    if let Some(ndc) = cursor_moved.and_then(|event| screen_to_ndc(camera, event.position)) {
+        cursor.pos = ndc * half_size;
         cursor.moved = true;
-    } else {
-        cursor.moved = false;
     }
 
-    let scale = projection.single().scale;
-    let cam_translation = c_transform_query.single().compute_transform().translation;
-    cursor.world_pos = (cursor.pos * scale) + cam_translation.xy();
+    cursor.world_pos = ndc_to_world(camera, camera_transform, cursor.pos / half_size);
 
-    cursor.delta = (cursor.prev_pos - cursor.pos) * scale;
+    // How far the world under the cursor moved, with the camera held still
+    let prev_world_pos = ndc_to_world(camera, camera_transform, cursor.prev_pos / half_size);
+    cursor.delta = prev_world_pos - cursor.world_pos;
 }
 
 pub struct ColorUpdate {
     pub selected: bool,
     pub hovered: bool,
     pub stickable: bool,
+    pub goal: bool,
+    pub checkpoint: bool,
 }
 
 impl ColorUpdate {
     pub fn get_color(&self) -> Color {
-        let mut ret = match self.stickable {
-            true => Vec3::new(0.5, 0.5, 0.5),
-            false => Vec3::new(0.25, 0.25, 0.25),
+        let mut ret = match (self.goal, self.checkpoint, self.stickable) {
+            (true, _, _) => Vec3::new(0.6, 0.6, 0.6),
+            (false, true, _) => Vec3::new(0.1, 0.1, 0.1),
+            (false, false, true) => Vec3::new(0.5, 0.5, 0.5),
+            (false, false, false) => Vec3::new(0.25, 0.25, 0.25),
         };
 
         if self.selected {
@@ -203,4 +260,51 @@ pub fn preload_assets(
         "circle 4",
         meshes.add(shape::Circle::new(4.0).into()).into()
     );
-}
\ No newline at end of file
+}
+
+// Little endian helpers shared by the binary replay and ghost files
+pub fn push_vec2(bytes: &mut Vec<u8>, v: Vec2) {
+    bytes.extend_from_slice(&v.x.to_le_bytes());
+    bytes.extend_from_slice(&v.y.to_le_bytes());
+}
+
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, at: 0 }
+    }
+
+    pub fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let slice = self.bytes.get(self.at..self.at + n).ok_or_else(|| invalid_data("file is truncated"))?;
+        self.at += n;
+        Ok(slice)
+    }
+
+    pub fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn vec2(&mut self) -> io::Result<Vec2> {
+        Ok(Vec2::new(self.f32()?, self.f32()?))
+    }
+}